@@ -3,7 +3,7 @@ use std::{
 	sync::{Arc, LazyLock, nonpoison::RwLock},
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clap_config::ClapConfig;
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -26,7 +26,7 @@ pub struct Args {
 	#[arg(
 		long,
 		env,
-		help = "Will use this file for storing key data, instead of the OS keyring. DO NOT USE! ONLY USEFUL FOR DEBUGGING! MASSIVE SECURITY ISSUE"
+		help = "Will use this file for storing the access and refresh token in plain text, instead of the OS keyring. Anyone who can read it can keep using the login. DO NOT USE! ONLY USEFUL FOR DEBUGGING! MASSIVE SECURITY ISSUE"
 	)]
 	pub token_file: Option<Arc<str>>,
 
@@ -39,6 +39,57 @@ pub struct Args {
 
 	#[arg(short, long, env, help = "Enable debug mode")]
 	pub debug: bool,
+
+	#[arg(
+		long,
+		env,
+		help = "Base url of the Helix API, e.g. a local mock server. Overrides the config"
+	)]
+	pub helix_url: Option<Arc<str>>,
+
+	#[arg(
+		long,
+		env,
+		help = "Base url of the Twitch OAuth2 endpoints, e.g. a local mock server. Overrides the config"
+	)]
+	pub oauth_url: Option<Arc<str>>,
+
+	#[arg(
+		long,
+		env,
+		help = "Url of the EventSub websocket, e.g. a local mock server. Overrides the config"
+	)]
+	pub eventsub_url: Option<Arc<str>>,
+
+	#[arg(
+		long,
+		env,
+		help = "Url of an IRC over websocket server to use for chat, e.g. a local mock server. Overrides the config"
+	)]
+	pub irc_url: Option<Arc<str>>,
+
+	#[arg(
+		long,
+		env,
+		help = "Start in simulation mode, replies to injected chat only show up in the transcript while real chat keeps working"
+	)]
+	pub simulate: bool,
+
+	#[arg(
+		long,
+		value_name = "USER: MESSAGE",
+		help = "Inject a chat message after startup, can be repeated. Implies --simulate"
+	)]
+	pub simulate_chat: Vec<Arc<str>>,
+
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, Subcommand, Deserialize, Serialize)]
+pub enum Command {
+	/// Log in by entering a code on another device, then exit. Useful on headless machines or over SSH
+	Login,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -65,6 +116,16 @@ impl Default for ObsConfig {
 	}
 }
 
+/// Custom urls for the twitch services, e.g. a local mock server. Unset ones talk to twitch
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EndpointConfig {
+	pub helix_url: Option<Box<str>>,
+	pub oauth_url: Option<Box<str>>,
+	pub eventsub_url: Option<Box<str>>,
+	/// IRC over websocket, ws:// or wss://
+	pub irc_url: Option<Box<str>>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, Type)]
 pub struct TtsConfig {
 	pub backend: TtsBackend,
@@ -80,6 +141,31 @@ impl Default for TtsConfig {
 	}
 }
 
+/// Rewards that get paused and resumed together
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+pub struct RewardGroup {
+	pub name: Box<str>,
+	/// Reward ids
+	pub rewards: Vec<Box<str>>,
+	/// Paused while offline
+	#[serde(default)]
+	pub live_only: bool,
+	/// Only enabled while streaming one of these categories, empty means any
+	#[serde(default)]
+	pub categories: Vec<Box<str>>,
+}
+
+/// Enables or disables all actions with a tag when the stream starts or ends
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+pub struct ActionGroupHook {
+	pub tag: Box<str>,
+	/// true enables the group, false disables it, None leaves it alone
+	#[serde(default)]
+	pub on_stream_start: Option<bool>,
+	#[serde(default)]
+	pub on_stream_end: Option<bool>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
 	pub use_os_color: Option<bool>,
@@ -87,8 +173,17 @@ pub struct Config {
 	pub enable_redeems: Option<bool>,
 	pub obs: Option<ObsConfig>,
 	pub tts: Option<TtsConfig>,
-	// used to track the scopes the token was last initialized with
-	// if changed the token should be forgotten
+	pub endpoints: Option<EndpointConfig>,
+	pub reward_groups: Option<Vec<RewardGroup>>,
+	/// All reward groups were paused from chat or the UI and stay paused until resumed
+	pub redeems_paused: Option<bool>,
+	/// Names of the reward groups paused from chat or the UI
+	pub paused_reward_groups: Option<Vec<Box<str>>>,
+	pub action_group_hooks: Option<Vec<ActionGroupHook>>,
+	/// Deleted actions are purged from the trash after this many days, 0 keeps them forever
+	pub trash_retention_days: Option<u32>,
+	// used to track the scopes the bot asked for last time
+	// if changed, the features needing the new ones stay disabled until the login is upgraded
 	pub scopes: Option<Vec<Scope>>,
 }
 
@@ -135,6 +230,12 @@ impl Default for Config {
 			enable_redeems: Some(true),
 			obs: Some(ObsConfig::default()),
 			tts: Some(TtsConfig::default()),
+			endpoints: Some(EndpointConfig::default()),
+			reward_groups: Some(Vec::new()),
+			redeems_paused: Some(false),
+			paused_reward_groups: Some(Vec::new()),
+			action_group_hooks: Some(Vec::new()),
+			trash_retention_days: Some(30),
 			scopes: Some(vec![
 				Scope::ChatEdit,
 				Scope::ChatRead,
@@ -145,6 +246,8 @@ impl Default for Config {
 				Scope::ModeratorReadChatters,
 				Scope::ModeratorReadVips,
 				Scope::ChannelReadSubscriptions,
+				Scope::ModeratorReadFollowers,
+				Scope::BitsRead,
 			]),
 		}
 	}
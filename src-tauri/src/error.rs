@@ -6,8 +6,11 @@ use std::{
 use serde::Serialize;
 use specta::Type;
 use twitch_api::{client::CompatError, helix};
-use twitch_irc::{SecureTCPTransport, login::StaticLoginCredentials};
-use twitch_oauth2::tokens::errors::{DeviceUserTokenExchangeError, ValidationError};
+use twitch_oauth2::tokens::errors::{
+	DeviceUserTokenExchangeError, RefreshTokenError, RetrieveTokenError, ValidationError,
+};
+
+use crate::twitch::{chat::ChatCredentials, endpoints::ChatTransport};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -30,6 +33,16 @@ pub enum ErrorMsg {
 	ObsWS,
 	ObsOverlay,
 	Tts,
+	RewardNotManageable,
+	MissingScope,
+	PassphraseNeeded,
+	UserNotFound,
+	CantBanSelf,
+	/// The action needs a prompt, e.g. the user to time out
+	MissingPrompt,
+	/// A timeout or ban action that wasn't set up with a target
+	MissingTarget,
+	Moderation,
 }
 
 impl From<Error> for ErrorMsg {
@@ -66,7 +79,7 @@ impl Error {
 
 	/// Only sets the message if the error is unknown
 	pub fn try_set_msg(mut self, msg: ErrorMsg) -> Self {
-		if msg == ErrorMsg::Unknown {
+		if self.msg == ErrorMsg::Unknown {
 			self.msg = msg;
 		}
 
@@ -141,6 +154,18 @@ impl From<DeviceUserTokenExchangeError<CompatError<reqwest::Error>>> for Error {
 	}
 }
 
+impl From<RefreshTokenError<CompatError<reqwest::Error>>> for Error {
+	fn from(value: RefreshTokenError<CompatError<reqwest::Error>>) -> Self {
+		Self::from_err(value.into(), ErrorMsg::TwitchAuth)
+	}
+}
+
+impl From<RetrieveTokenError<CompatError<reqwest::Error>>> for Error {
+	fn from(value: RetrieveTokenError<CompatError<reqwest::Error>>) -> Self {
+		Self::from_err(value.into(), ErrorMsg::TwitchAuth)
+	}
+}
+
 impl From<toml::ser::Error> for Error {
 	fn from(value: toml::ser::Error) -> Self {
 		Self::from_err(value.into(), ErrorMsg::Unknown)
@@ -153,8 +178,14 @@ impl From<toml::de::Error> for Error {
 	}
 }
 
-impl From<twitch_irc::Error<SecureTCPTransport, StaticLoginCredentials>> for Error {
-	fn from(value: twitch_irc::Error<SecureTCPTransport, StaticLoginCredentials>) -> Self {
+impl From<serde_json::Error> for Error {
+	fn from(value: serde_json::Error) -> Self {
+		Self::from_err(value.into(), ErrorMsg::Unknown)
+	}
+}
+
+impl From<twitch_irc::Error<ChatTransport, ChatCredentials>> for Error {
+	fn from(value: twitch_irc::Error<ChatTransport, ChatCredentials>) -> Self {
 		Self::from_err(value.into(), ErrorMsg::ChatMsgSend)
 	}
 }
@@ -195,6 +226,12 @@ impl From<tts::Error> for Error {
 	}
 }
 
+impl From<notify::Error> for Error {
+	fn from(value: notify::Error) -> Self {
+		Self::from_err(value.into(), ErrorMsg::Unknown)
+	}
+}
+
 impl From<reqwest::Error> for Error {
 	fn from(value: reqwest::Error) -> Self {
 		Self::from_err(value.into(), ErrorMsg::Unknown)
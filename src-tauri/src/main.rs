@@ -6,7 +6,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use beanybot_lib::{
-	config::{CONFIG, Config},
+	config::{ARGS, CONFIG, Command, Config},
 	twitch,
 };
 
@@ -17,8 +17,8 @@ fn setup_config() {
 	let def_config = Config::default();
 	let mut disk_config = CONFIG.write();
 	if !def_config.equal_scope(&disk_config) {
-		tracing::debug!("Changed scopes detected. Forgetting old token");
-		_ = twitch::auth::forget_token();
+		// the old token keeps working, only the features needing the new scopes are disabled
+		tracing::info!("Changed scopes detected. Upgrade the login to enable all features");
 
 		disk_config.scopes = def_config.scopes;
 		if let Err(e) = disk_config.save() {
@@ -31,5 +31,18 @@ fn main() {
 	logs::setup_logging();
 	setup_config();
 
+	if let Err(e) = twitch::endpoints::check_endpoints() {
+		eprintln!("{e}");
+		std::process::exit(1);
+	}
+
+	if let Some(Command::Login) = ARGS.command {
+		if let Err(e) = ::tauri::async_runtime::block_on(twitch::auth::cli_login()) {
+			eprintln!("Login failed: {e}");
+			std::process::exit(1);
+		}
+		return;
+	}
+
 	tauri::run()
 }
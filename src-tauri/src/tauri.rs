@@ -1,18 +1,30 @@
 use std::sync::Arc;
 
 use beanybot_lib::{
-	config::{CONFIG, TtsConfig},
+	config::{ActionGroupHook, CONFIG, RewardGroup, TtsConfig},
 	error::ErrorMsg,
 	obs,
 	os_color::{ColorSchemeAccent, get_color_scheme},
 	tts::VoiceData,
 	twitch::{
 		self, TWITCH_CLIENT,
-		actions::{Action, ArcStr, toggle_disable_action as toggle_action},
-		auth::{forget_token, load_token},
-		chat::get_random_chatter,
+		action_watcher::ActionsChangedEvent,
+		actions::{
+			Action, ActionFileReport, ArcStr, ExecCtx, Trigger,
+			toggle_disable_action as toggle_action,
+		},
+		auth::{AuthFailureEvent, forget_token, load_token, save_token},
+		bundles::{ImportConflict, ImportReport},
+		chat::{ChatHealth, ChatHealthEvent, get_chat_health as chat_health, get_random_chatter},
+		history::{HistoryEntry, HistoryEvent, HistoryFilter},
+		redeem_queue::{PendingRedemption, RedeemQueueEvent},
+		redeems::{FrontendReward, RewardSettings, is_manageable},
+		scopes::Feature,
+		simulator::{TranscriptEvent, TranscriptLine},
+		stream::{StreamSession, StreamSessionEvent},
+		trash::TrashedAction,
 	},
-	utils::{CFG_DIR_PATH, NAME_CAPITALIZED},
+	utils::{APP_HANDLE, CFG_DIR_PATH, NAME_CAPITALIZED},
 };
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -74,6 +86,57 @@ async fn is_logged_in() -> Option<String> {
 	}
 }
 
+/// Features the login is missing scopes for
+#[tauri::command]
+#[specta::specta]
+async fn get_disabled_features() -> Vec<Feature> {
+	TWITCH_CLIENT.read().await.disabled_features()
+}
+
+/// Runs the device flow again, only asking for the missing scopes on top of the current ones
+#[tauri::command]
+#[specta::specta]
+async fn upgrade_scopes() -> Result<(), ErrorMsg> {
+	let tkn = match twitch::auth::upgrade_scopes().await {
+		Err(e) => {
+			tracing::error!("Error upgrading scopes: {e}");
+			return Err(e.msg);
+		}
+		Ok(t) => t,
+	};
+
+	TWITCH_CLIENT.write().await.set_token(tkn).await;
+	Ok(())
+}
+
+/// The keyring is unavailable and the encrypted token file needs a passphrase
+#[tauri::command]
+#[specta::specta]
+fn token_passphrase_needed() -> bool {
+	twitch::token_store::passphrase_missing()
+}
+
+/// Passphrase for the encrypted token file, used when there is no keyring.
+/// Saves the current login with it, in case it couldn't be saved without one.
+#[tauri::command]
+#[specta::specta]
+async fn set_token_passphrase(passphrase: String) -> Result<(), ErrorMsg> {
+	twitch::token_store::set_passphrase(passphrase);
+
+	let token = match TWITCH_CLIENT.read().await.get_token() {
+		None => return Ok(()),
+		Some(t) => t,
+	};
+
+	match save_token(&token).await {
+		Err(e) => {
+			tracing::error!("Couldn't save the token with the passphrase: {e}");
+			Err(e.msg)
+		}
+		Ok(()) => Ok(()),
+	}
+}
+
 #[tauri::command]
 #[specta::specta]
 async fn logout() {
@@ -97,15 +160,150 @@ fn redeems_enabled() -> bool {
 
 #[tauri::command]
 #[specta::specta]
-async fn get_all_actions() -> Vec<Action> {
-	twitch::actions::get_all_actions().await
+async fn get_all_actions(tag: Option<String>) -> Vec<Action> {
+	twitch::actions::get_all_actions(tag.as_deref()).await
+}
+
+/// Deleted actions that can still be restored, newest first
+#[tauri::command]
+#[specta::specta]
+fn list_trash() -> Result<Vec<TrashedAction>, ErrorMsg> {
+	match twitch::trash::list_trash() {
+		Err(e) => {
+			tracing::error!("Couldn't list the trash: {e}");
+			Err(e.msg)
+		}
+		Ok(trashed) => Ok(trashed),
+	}
+}
+
+/// Returns the trigger of the restored action
+#[tauri::command]
+#[specta::specta]
+async fn restore_action(id: String) -> Result<String, ErrorMsg> {
+	match twitch::trash::restore(id.as_str()).await {
+		Err(e) => {
+			tracing::error!("Couldn't restore {id}: {e}");
+			Err(e.msg)
+		}
+		Ok(key) => Ok(key),
+	}
 }
 
 #[tauri::command]
 #[specta::specta]
-async fn add_action(action: Action) {
+fn get_action_tags() -> Vec<String> {
+	twitch::actions::get_tags()
+}
+
+/// Enables or disables every action with the tag, returns how many have it
+#[tauri::command]
+#[specta::specta]
+fn set_action_group_disabled(tag: String, disabled: bool) -> u32 {
+	twitch::actions::set_tag_disabled(tag.as_str(), disabled) as u32
+}
+
+#[tauri::command]
+#[specta::specta]
+fn get_action_group_hooks() -> Vec<ActionGroupHook> {
+	twitch::action_groups::get_action_group_hooks()
+}
+
+#[tauri::command]
+#[specta::specta]
+fn set_action_group_hooks(hooks: Vec<ActionGroupHook>) -> Result<(), ErrorMsg> {
+	let mut config = CONFIG.write();
+	config.action_group_hooks = Some(hooks);
+	config.save()?;
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn add_action(action: Action) -> Result<(), ErrorMsg> {
 	tracing::info!("Saving action: {action:?}");
-	twitch::actions::add_action(action).await
+	match twitch::actions::add_action(action).await {
+		Err(e) => {
+			tracing::error!("Error saving action: {e}");
+			Err(e.msg)
+		}
+		Ok(()) => Ok(()),
+	}
+}
+
+/// Runs the action like the simulator would with a fake user and prompt and returns what it did.
+/// Replies only show up in the transcript and nothing is sent to twitch.
+/// With dry_run nothing is changed either, it only shows what would happen.
+#[tauri::command]
+#[specta::specta]
+async fn test_action(
+	mut action: Action,
+	user: String,
+	prompt: Option<String>,
+	dry_run: bool,
+) -> Vec<String> {
+	let ctx = ExecCtx {
+		dry_run,
+		..ExecCtx::new(&user, prompt.as_deref()).simulated()
+	};
+
+	// a dry run didn't do anything worth keeping in the history
+	let res = match dry_run {
+		true => action.exec.exec(&ctx).await,
+		false => action.run(&ctx).await,
+	};
+
+	let mut trace = ctx.trace();
+	if let Err(e) = res {
+		tracing::warn!("Test run of {:?} failed: {e}", action.trigger);
+		trace.push(format!("Failed: {e}"));
+	}
+	trace
+}
+
+/// Writes the actions with the given keys to a bundle file and returns how many were written
+#[tauri::command]
+#[specta::specta]
+async fn export_actions(keys: Vec<String>, path: String) -> Result<u32, ErrorMsg> {
+	match twitch::bundles::export_bundle(&keys, path.as_ref()).await {
+		Err(e) => {
+			tracing::error!("Couldn't export actions: {e}");
+			Err(e.msg)
+		}
+		Ok(count) => Ok(count as u32),
+	}
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn import_actions(path: String, conflict: ImportConflict) -> Result<ImportReport, ErrorMsg> {
+	match twitch::bundles::import_bundle(path.as_ref(), conflict).await {
+		Err(e) => {
+			tracing::error!("Couldn't import actions: {e}");
+			Err(e.msg)
+		}
+		Ok(report) => Ok(report),
+	}
+}
+
+/// Every action file with whether it loaded, and where broken ones were moved to
+#[tauri::command]
+#[specta::specta]
+fn get_action_load_report() -> Vec<ActionFileReport> {
+	twitch::actions::get_load_report()
+}
+
+/// Past action runs matching the filter, newest first
+#[tauri::command]
+#[specta::specta]
+fn get_history(filter: HistoryFilter) -> Result<Vec<HistoryEntry>, ErrorMsg> {
+	match twitch::history::query(&filter) {
+		Err(e) => {
+			tracing::error!("Couldn't read action history: {e}");
+			Err(e.msg)
+		}
+		Ok(entries) => Ok(entries),
+	}
 }
 
 #[tauri::command]
@@ -121,6 +319,24 @@ async fn get_rand_chatter() -> Option<Box<str>> {
 	get_random_chatter().await
 }
 
+#[tauri::command]
+#[specta::specta]
+fn get_chat_health() -> ChatHealth {
+	chat_health()
+}
+
+#[tauri::command]
+#[specta::specta]
+fn get_stream_session() -> Option<StreamSession> {
+	twitch::stream::get_stream_session()
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_stream_chatters() -> Vec<Box<str>> {
+	twitch::chat::get_stream_chatters().await
+}
+
 #[tauri::command]
 #[specta::specta]
 fn open_log_dir() -> Result<(), String> {
@@ -161,6 +377,7 @@ struct FrontendRedeem {
 	color: String,
 	name: String,
 	cost: usize,
+	manageable: bool,
 }
 
 #[tauri::command]
@@ -176,6 +393,7 @@ async fn get_redeems() -> Result<Vec<FrontendRedeem>, ErrorMsg> {
 		Ok(redeems) => Ok(redeems
 			.into_iter()
 			.map(|red| FrontendRedeem {
+				manageable: is_manageable(red.id.as_str()),
 				color: red.background_color,
 				cost: red.cost,
 				id: red.id.to_string(),
@@ -185,6 +403,230 @@ async fn get_redeems() -> Result<Vec<FrontendRedeem>, ErrorMsg> {
 	}
 }
 
+#[tauri::command]
+#[specta::specta]
+async fn get_rewards() -> Result<Vec<FrontendReward>, ErrorMsg> {
+	let res = TWITCH_CLIENT.read().await.update_redeems().await;
+	match res {
+		Err(e) => {
+			tracing::error!("Error getting twitch rewards: {e}");
+			Err(ErrorMsg::RedeemRequest)
+		}
+		Ok(rewards) => Ok(rewards.into_iter().map(FrontendReward::from).collect()),
+	}
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn create_reward(settings: RewardSettings) -> Result<FrontendReward, ErrorMsg> {
+	let mut client = TWITCH_CLIENT.write().await;
+	let reward = match client.create_reward(settings).await {
+		Err(e) => {
+			let e = e.try_set_msg(ErrorMsg::RedeemRequest);
+			tracing::error!("Error creating reward: {e}");
+			return Err(e.msg);
+		}
+		Ok(r) => r,
+	};
+
+	if let Err(e) = client.refresh_redeems().await {
+		tracing::warn!("Couldn't refresh rewards: {e}");
+	}
+
+	Ok(FrontendReward::from(reward))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn update_reward(id: String, settings: RewardSettings) -> Result<(), ErrorMsg> {
+	let mut client = TWITCH_CLIENT.write().await;
+	if let Err(e) = client.update_reward(&id, settings).await {
+		let e = e.try_set_msg(ErrorMsg::RedeemRequest);
+		tracing::error!("Error updating reward {id}: {e}");
+		return Err(e.msg);
+	}
+
+	if let Err(e) = client.refresh_redeems().await {
+		tracing::warn!("Couldn't refresh rewards: {e}");
+	}
+
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn delete_reward(id: String) -> Result<(), ErrorMsg> {
+	let mut client = TWITCH_CLIENT.write().await;
+	if let Err(e) = client.delete_reward(&id).await {
+		let e = e.try_set_msg(ErrorMsg::RedeemRequest);
+		tracing::error!("Error deleting reward {id}: {e}");
+		return Err(e.msg);
+	}
+
+	if let Err(e) = client.refresh_redeems().await {
+		tracing::warn!("Couldn't refresh rewards: {e}");
+	}
+
+	Ok(())
+}
+
+/// Actions linked to rewards that don't exist anymore
+#[tauri::command]
+#[specta::specta]
+async fn get_orphaned_actions() -> Result<Vec<Action>, ErrorMsg> {
+	let res = TWITCH_CLIENT.read().await.update_redeems().await;
+	match res {
+		Err(e) => {
+			tracing::error!("Error getting twitch rewards: {e}");
+			Err(ErrorMsg::RedeemRequest)
+		}
+		Ok(rewards) => Ok(twitch::redeems::orphaned_redeem_actions(&rewards).await),
+	}
+}
+
+/// Links orphaned actions to the reward with the same title.
+/// Returns the old and new reward ids.
+#[tauri::command]
+#[specta::specta]
+async fn relink_redeem_actions() -> Result<Vec<(String, String)>, ErrorMsg> {
+	let res = TWITCH_CLIENT.read().await.update_redeems().await;
+	match res {
+		Err(e) => {
+			tracing::error!("Error getting twitch rewards: {e}");
+			Err(ErrorMsg::RedeemRequest)
+		}
+		Ok(rewards) => Ok(twitch::redeems::relink_redeem_actions(&rewards).await),
+	}
+}
+
+#[tauri::command]
+#[specta::specta]
+fn is_simulating() -> bool {
+	twitch::simulator::is_simulating()
+}
+
+#[tauri::command]
+#[specta::specta]
+fn set_simulating(simulating: bool) {
+	twitch::simulator::set_simulating(simulating);
+}
+
+#[tauri::command]
+#[specta::specta]
+fn get_transcript() -> Vec<TranscriptLine> {
+	twitch::simulator::get_transcript()
+}
+
+#[tauri::command]
+#[specta::specta]
+fn clear_transcript() {
+	twitch::simulator::clear_transcript();
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn simulate_chat(user: String, text: String, badges: Vec<String>) -> Result<(), ErrorMsg> {
+	if let Err(e) = twitch::simulator::simulate_chat(&user, &text, &badges).await {
+		tracing::error!("Error simulating chat message: {e}");
+		return Err(e.msg);
+	}
+	Ok(())
+}
+
+/// The reward can be given by id or title
+#[tauri::command]
+#[specta::specta]
+async fn simulate_redeem(user: String, reward: String, prompt: String) {
+	twitch::simulator::simulate_redeem(&user, &reward, &prompt).await;
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn simulate_event(
+	trigger: Trigger,
+	user: String,
+	prompt: Option<String>,
+	amount: Option<u64>,
+) -> Result<(), ErrorMsg> {
+	if let Err(e) =
+		twitch::simulator::simulate_event(trigger, &user, prompt.as_deref(), amount).await
+	{
+		tracing::error!("Error simulating event: {e}");
+		return Err(e.msg);
+	}
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+fn get_reward_groups() -> Vec<RewardGroup> {
+	twitch::reward_groups::get_reward_groups()
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn set_reward_groups(groups: Vec<RewardGroup>) -> Result<(), ErrorMsg> {
+	{
+		let mut config = CONFIG.write();
+		config.reward_groups = Some(groups);
+		config.save()?;
+	}
+
+	twitch::reward_groups::apply_reward_groups().await;
+	Ok(())
+}
+
+/// Pauses or resumes a reward group until it is resumed again, or all of them if no name is given
+#[tauri::command]
+#[specta::specta]
+async fn set_reward_group_paused(name: Option<String>, paused: bool) -> bool {
+	twitch::reward_groups::set_paused(name.as_deref(), paused).await
+}
+
+#[tauri::command]
+#[specta::specta]
+fn get_redeem_queue() -> Vec<PendingRedemption> {
+	twitch::redeem_queue::get_redeem_queue()
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn approve_redemption(id: String) -> Result<(), ErrorMsg> {
+	if let Err(e) = twitch::redeem_queue::approve_redemption(&id).await {
+		let e = e.try_set_msg(ErrorMsg::RedeemRequest);
+		tracing::error!("Error approving redemption {id}: {e}");
+		return Err(e.msg);
+	}
+
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn reject_redemption(id: String) -> Result<(), ErrorMsg> {
+	if let Err(e) = twitch::redeem_queue::reject_redemption(&id).await {
+		let e = e.try_set_msg(ErrorMsg::RedeemRequest);
+		tracing::error!("Error rejecting redemption {id}: {e}");
+		return Err(e.msg);
+	}
+
+	Ok(())
+}
+
+/// Returns the ids that couldn't be approved
+#[tauri::command]
+#[specta::specta]
+async fn approve_redemptions(ids: Vec<String>) -> Vec<String> {
+	twitch::redeem_queue::approve_redemptions(&ids).await
+}
+
+/// Returns the ids that couldn't be rejected
+#[tauri::command]
+#[specta::specta]
+async fn reject_redemptions(ids: Vec<String>) -> Vec<String> {
+	twitch::redeem_queue::reject_redemptions(&ids).await
+}
+
 #[tauri::command]
 #[specta::specta]
 async fn toggle_disable_action(key: Box<str>) -> Option<bool> {
@@ -303,12 +745,51 @@ pub fn run() {
 			logout,
 			get_accent_color,
 			get_all_actions,
+			get_action_tags,
+			set_action_group_disabled,
+			get_action_group_hooks,
+			set_action_group_hooks,
 			add_action,
 			remove_action,
+			list_trash,
+			restore_action,
+			test_action,
+			get_history,
+			get_action_load_report,
+			export_actions,
+			import_actions,
 			get_rand_chatter,
+			get_chat_health,
+			get_stream_session,
+			get_stream_chatters,
 			open_log_dir,
 			get_current_logs,
 			get_redeems,
+			get_rewards,
+			create_reward,
+			update_reward,
+			delete_reward,
+			token_passphrase_needed,
+			set_token_passphrase,
+			get_disabled_features,
+			upgrade_scopes,
+			get_orphaned_actions,
+			relink_redeem_actions,
+			is_simulating,
+			set_simulating,
+			get_transcript,
+			clear_transcript,
+			simulate_chat,
+			simulate_redeem,
+			simulate_event,
+			get_reward_groups,
+			set_reward_groups,
+			set_reward_group_paused,
+			get_redeem_queue,
+			approve_redemption,
+			reject_redemption,
+			approve_redemptions,
+			reject_redemptions,
 			toggle_disable_action,
 			redeems_enabled,
 			connect_obs,
@@ -320,7 +801,16 @@ pub fn run() {
 			get_tts_cfg,
 			download_piper_voice
 		])
-		.events(collect_events![DownloadEvent]);
+		.events(collect_events![
+			DownloadEvent,
+			ChatHealthEvent,
+			StreamSessionEvent,
+			RedeemQueueEvent,
+			AuthFailureEvent,
+			TranscriptEvent,
+			HistoryEvent,
+			ActionsChangedEvent
+		]);
 
 	#[cfg(debug_assertions)] // <- Only export on non-release builds
 	{
@@ -369,6 +859,10 @@ pub fn run() {
 				.build(app)?;
 			// This is required if you want to use events
 			builder.mount_events(app);
+			_ = APP_HANDLE.set(app.handle().clone());
+			tauri::async_runtime::spawn(twitch::simulator::replay_cli_chat());
+			tauri::async_runtime::spawn(twitch::action_watcher::watch_actions());
+			tauri::async_runtime::spawn(twitch::trash::purge_periodically());
 			Ok(())
 		})
 		.on_window_event(|window, event| {
@@ -1,47 +1,70 @@
-use std::{
-	sync::{Arc, LazyLock},
-	time::SystemTime,
-};
+use std::sync::{Arc, LazyLock};
 
-use tauri::async_runtime::{JoinHandle, RwLock};
+use tauri::async_runtime::RwLock;
 use twitch_api::{
 	HelixClient,
 	helix::{points::CustomReward, users::User},
 };
-use twitch_irc::{SecureTCPTransport, TwitchIRCClient, login::StaticLoginCredentials};
+use twitch_irc::TwitchIRCClient;
 use twitch_oauth2::UserToken;
 
-use crate::twitch::chat::chat_listener;
+use crate::twitch::{
+	auth::{TokenRefresher, token_refresher},
+	chat::{ChatCredentials, ChatSupervisor, chat_listener},
+	endpoints::{ChatTransport, EndpointClient},
+	events::EventSubSupervisor,
+};
 
+pub mod action_groups;
+pub mod action_watcher;
 pub mod actions;
 pub mod auth;
+pub mod bundles;
 pub mod chat;
 pub mod counter;
+pub mod endpoints;
 pub mod events;
+pub mod history;
+pub mod notifications;
+pub mod redeem_queue;
 pub mod redeems;
+pub mod reward_groups;
+pub mod scopes;
+pub mod simulator;
+pub mod stream;
+pub mod token_store;
+pub mod trash;
 pub mod users;
 
-type IrcClient = TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>;
+type IrcClient = TwitchIRCClient<ChatTransport, ChatCredentials>;
 
 pub static TWITCH_CLIENT: LazyLock<Arc<RwLock<TwitchClient>>> =
 	LazyLock::new(|| Arc::new(RwLock::new(TwitchClient::new())));
 
-#[derive(Default)]
 pub struct TwitchClient {
-	client: HelixClient<'static, reqwest::Client>,
+	client: HelixClient<'static, EndpointClient>,
 	token: Option<Arc<UserToken>>,
+	token_refresher: Option<TokenRefresher>,
 	chat_client: Option<Arc<IrcClient>>,
-	chat_listener: Option<JoinHandle<()>>,
+	chat_listener: Option<ChatSupervisor>,
 	user_info: Option<User>,
 	websocket_id: Option<Box<str>>,
-	websocket_last_event: Option<SystemTime>, // TODO: check how long ago this is and potentially reconnect
+	eventsub: Option<EventSubSupervisor>,
 	redeems: Option<Vec<CustomReward>>,
 }
 
-impl Drop for TwitchClient {
-	fn drop(&mut self) {
-		if let Some(join_handle) = &self.chat_listener {
-			join_handle.abort();
+impl Default for TwitchClient {
+	fn default() -> Self {
+		Self {
+			client: HelixClient::with_client(EndpointClient::default()),
+			token: None,
+			token_refresher: None,
+			chat_client: None,
+			chat_listener: None,
+			user_info: None,
+			websocket_id: None,
+			eventsub: None,
+			redeems: None,
 		}
 	}
 }
@@ -67,34 +90,42 @@ impl TwitchClient {
 			Ok(info) => self.user_info = info,
 		}
 
-		self.token = Some(Arc::new(tkn));
+		self.token_refresher = Some(token_refresher(&tkn));
+		let tkn = Arc::new(tkn);
+		self.token = Some(tkn.clone());
 
-		if let Err(e) = Self::setup_websocket().await {
-			tracing::error!("Error setting up websocket: {e}");
-		};
+		// replacing the old supervisor closes its websocket
+		self.eventsub = Some(Self::setup_websocket());
 
-		match self.update_redeems().await {
-			Err(e) => tracing::error!("Error getting redeems: {e}"),
-			Ok(r) => self.redeems = Some(r),
+		if let Err(e) = self.refresh_redeems().await {
+			tracing::error!("Error getting redeems: {e}");
 		};
 
-		if let Some(join_handle) = &self.chat_listener {
-			join_handle.abort();
+		// replacing the old supervisor shuts it down
+		self.chat_listener = Some(chat_listener(&tkn));
+	}
+
+	/// Swaps in a refreshed token for the same account.
+	/// Helix and EventSub read the token on every request, chat keeps its connection and uses it for the next login.
+	pub fn update_token(&mut self, tkn: UserToken) {
+		if self.token.is_none() {
+			return; // logged out while refreshing
 		}
 
-		match chat_listener(self).await {
-			Ok(h) => self.chat_listener = Some(h),
-			Err(e) => tracing::error!("Error starting chat listener: {e}"),
-		};
+		if let Some(chat) = &self.chat_listener {
+			chat.update_token(&tkn);
+		}
+		self.token = Some(Arc::new(tkn));
 	}
 
 	pub fn forget_token(&mut self) {
-		if let Some(handler) = &self.chat_listener {
-			handler.abort();
-		}
+		// dropping the supervisor parts the channel and closes the connection
 		self.chat_listener = None;
+		self.chat_client = None;
+		self.token_refresher = None;
 		self.token = None;
 		self.user_info = None;
+		self.eventsub = None;
 		self.websocket_id = None;
 		self.redeems = None;
 	}
@@ -0,0 +1,47 @@
+use crate::{
+	config::{ActionGroupHook, CONFIG},
+	twitch::actions::set_tag_disabled,
+};
+
+pub fn get_action_group_hooks() -> Vec<ActionGroupHook> {
+	CONFIG.read().action_group_hooks.clone().unwrap_or_default()
+}
+
+/// Applies the hooks for the stream starting or ending
+pub fn on_stream_change(live: bool) {
+	for hook in get_action_group_hooks() {
+		let enable = match live {
+			true => hook.on_stream_start,
+			false => hook.on_stream_end,
+		};
+
+		if let Some(enable) = enable {
+			set_tag_disabled(hook.tag.as_ref(), !enable);
+		}
+	}
+}
+
+/// Handles `!group off|on <tag>` from chat
+pub fn chat_command(args: &str) -> String {
+	let mut split = args.split_whitespace();
+	let disabled = match split.next() {
+		Some("off") => true,
+		Some("on") => false,
+		_ => return String::from("Usage: !group off|on <tag>"),
+	};
+
+	let tag = match split.next() {
+		None => return String::from("Usage: !group off|on <tag>"),
+		Some(t) => t,
+	};
+
+	let state = match disabled {
+		true => "disabled",
+		false => "enabled",
+	};
+
+	match set_tag_disabled(tag, disabled) {
+		0 => format!("There are no actions tagged {tag}"),
+		count => format!("{count} actions tagged {tag} {state}"),
+	}
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashSet, ops::Deref, path::PathBuf, time::Duration};
+
+use notify::{Event as FsEvent, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri_specta::Event;
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+	error::Error,
+	twitch::actions::{ActionLoadError, FileReload, reload_action_file},
+	utils::{ACTION_DIR, emit_event},
+};
+
+// editors often write a file in several steps
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ActionFileError {
+	pub file: String,
+	pub error: ActionLoadError,
+}
+
+/// Action files were changed outside of the bot
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type, Event)]
+pub struct ActionsChangedEvent {
+	/// Keys of the added or changed actions
+	pub updated: Vec<String>,
+	pub removed: Vec<String>,
+	pub errors: Vec<ActionFileError>,
+}
+
+/// Keeps the action table in sync with the actions directory
+pub async fn watch_actions() {
+	if let Err(e) = internal_watch_actions().await {
+		tracing::error!("Couldn't watch the actions directory: {e}");
+	}
+}
+
+async fn internal_watch_actions() -> Result<(), Error> {
+	std::fs::create_dir_all(ACTION_DIR.as_path())?;
+
+	let (tx, mut rx) = mpsc::unbounded_channel();
+	let mut watcher = notify::recommended_watcher(move |res: notify::Result<FsEvent>| {
+		_ = tx.send(res);
+	})?;
+	watcher.watch(ACTION_DIR.as_path(), RecursiveMode::NonRecursive)?;
+
+	tracing::info!("Watching {:?} for action changes", ACTION_DIR.as_path());
+
+	while let Some(res) = rx.recv().await {
+		let mut paths = HashSet::new();
+		collect_paths(res, &mut paths);
+
+		sleep(DEBOUNCE).await;
+		while let Ok(res) = rx.try_recv() {
+			collect_paths(res, &mut paths);
+		}
+
+		reload(paths);
+	}
+
+	Ok(())
+}
+
+fn collect_paths(res: notify::Result<FsEvent>, paths: &mut HashSet<PathBuf>) {
+	let event = match res {
+		Err(e) => {
+			tracing::warn!("Error watching actions: {e}");
+			return;
+		}
+		Ok(ev) => ev,
+	};
+
+	if event.kind.is_access() {
+		return;
+	}
+
+	paths.extend(event.paths.into_iter().filter(|path| {
+		path.extension()
+			.and_then(|extension| extension.to_str())
+			.is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+	}));
+}
+
+fn reload(paths: HashSet<PathBuf>) {
+	let mut changes = ActionsChangedEvent::default();
+
+	for path in paths {
+		match reload_action_file(&path) {
+			Ok(FileReload::Unchanged) => {}
+			Ok(FileReload::Updated(key)) => {
+				tracing::info!("Reloaded action {}", key.deref());
+				changes.updated.push(key.deref().to_string());
+			}
+			Ok(FileReload::Removed(key)) => {
+				tracing::info!("Removed action {}", key.deref());
+				changes.removed.push(key.deref().to_string());
+			}
+			Ok(FileReload::Broken(key, e)) => {
+				tracing::warn!(
+					"Unloaded action {} because {path:?} is broken: {e}",
+					key.deref()
+				);
+				changes.removed.push(key.deref().to_string());
+				changes.errors.push(ActionFileError {
+					file: path.display().to_string(),
+					error: e,
+				});
+			}
+			Err(e) => {
+				tracing::warn!("Couldn't load action file {path:?}: {e}");
+				changes.errors.push(ActionFileError {
+					file: path.display().to_string(),
+					error: e,
+				});
+			}
+		}
+	}
+
+	if changes.updated.is_empty() && changes.removed.is_empty() && changes.errors.is_empty() {
+		return;
+	}
+
+	emit_event(changes);
+}
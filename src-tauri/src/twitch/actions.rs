@@ -1,11 +1,14 @@
 use std::{
 	borrow::{Borrow, Cow},
+	fmt::Display,
 	fs::{self, OpenOptions, create_dir_all, read_dir, remove_file},
 	io::Write,
 	ops::Deref,
+	path::{Path, PathBuf},
 	sync::{
 		Arc, LazyLock,
 		atomic::{AtomicU64, Ordering},
+		nonpoison::{Mutex, RwLock},
 	},
 	time::{SystemTime, UNIX_EPOCH},
 };
@@ -15,17 +18,125 @@ use rand::Rng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use uuid::Uuid;
 
 use crate::{
-	error::Error,
+	error::{Error, ErrorMsg},
 	tts,
-	twitch::{TWITCH_CLIENT, counter::TwitchCounter},
-	utils::ACTION_DIR,
+	twitch::{
+		TWITCH_CLIENT, chat,
+		counter::TwitchCounter,
+		history::{self, ExecStatus, HistoryEntry},
+		stream, trash,
+		users::BanResult,
+	},
+	utils::{ACTION_DIR, NAME_CAPITALIZED, get_unix, get_unix_milli},
 };
 
 static ACTION_TABLE: LazyLock<DashMap<ArcStr, Action>> =
 	LazyLock::new(|| init_map().unwrap_or_default());
 
+/// Version of the action file format, bump it and add a migration when an old file wouldn't parse anymore
+pub const ACTION_VERSION: u32 = 1;
+
+/// Upgrades a file from the version at its index to the next one
+const MIGRATIONS: [fn(&mut toml::Table); ACTION_VERSION as usize] = [
+	// files from before versioning, the format itself didn't change
+	|_| {},
+];
+
+// file name of each action, the trigger itself is only stored inside the file
+static ACTION_FILES: LazyLock<DashMap<ArcStr, Box<str>>> = LazyLock::new(DashMap::new);
+
+static BACKUP_DIR: LazyLock<PathBuf> = LazyLock::new(|| ACTION_DIR.join("backups"));
+
+// broken files are moved here, so saving an action with the same name can't overwrite them
+static QUARANTINE_DIR: LazyLock<PathBuf> = LazyLock::new(|| ACTION_DIR.join("quarantine"));
+
+// filled by init_map, kept up to date by reload_action_file
+static LOAD_REPORT: LazyLock<RwLock<Vec<ActionFileReport>>> =
+	LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Why an action file couldn't be loaded
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ActionLoadError {
+	pub message: String,
+	/// Starts at 1
+	pub line: Option<usize>,
+	/// Starts at 1
+	pub column: Option<usize>,
+	/// Made by a newer version of the bot, the file is left alone so it still works after upgrading
+	pub newer_version: bool,
+}
+
+impl ActionLoadError {
+	fn from_toml(e: &toml::de::Error, content: &str) -> Self {
+		let (line, column) = match e.span() {
+			None => (None, None),
+			Some(span) => {
+				let before = content.get(..span.start).unwrap_or(content);
+				let line = before.matches('\n').count() + 1;
+				let column = before
+					.rsplit('\n')
+					.next()
+					.unwrap_or_default()
+					.chars()
+					.count() + 1;
+				(Some(line), Some(column))
+			}
+		};
+
+		Self {
+			message: e.message().to_string(),
+			line,
+			column,
+			newer_version: false,
+		}
+	}
+}
+
+impl From<std::io::Error> for ActionLoadError {
+	fn from(value: std::io::Error) -> Self {
+		Self {
+			message: value.to_string(),
+			line: None,
+			column: None,
+			newer_version: false,
+		}
+	}
+}
+
+impl From<String> for ActionLoadError {
+	fn from(value: String) -> Self {
+		Self {
+			message: value,
+			line: None,
+			column: None,
+			newer_version: false,
+		}
+	}
+}
+
+impl Display for ActionLoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match (self.line, self.column) {
+			(Some(line), Some(column)) => write!(f, "{} at {line}:{column}", self.message),
+			_ => write!(f, "{}", self.message),
+		}
+	}
+}
+
+/// How loading a single action file went
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ActionFileReport {
+	pub file: String,
+	/// Key of the loaded action
+	pub key: Option<String>,
+	pub error: Option<ActionLoadError>,
+	/// Where the broken file was moved to
+	pub quarantined: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
 // wrapper around Arc<str> so that we can implement Type by hand, until builtin support is in specta
 pub struct ArcStr(Arc<str>);
@@ -82,12 +193,21 @@ impl Type for ArcStr {
 pub enum Trigger {
 	Command(ArcStr),
 	Redeem(ArcStr),
+	Follow,
+	Subscribe,
+	Resub,
+	GiftSub,
+	Cheer,
+	Raid,
+	StreamStart,
+	StreamEnd,
 }
 
 impl Trigger {
-	fn get_inner(&self) -> &ArcStr {
+	fn key(&self) -> ArcStr {
 		match self {
-			Trigger::Command(s) | Trigger::Redeem(s) => s,
+			Trigger::Command(s) | Trigger::Redeem(s) => s.clone(),
+			other => ArcStr::from(other.deref()),
 		}
 	}
 }
@@ -95,7 +215,90 @@ impl Trigger {
 impl Deref for Trigger {
 	type Target = str;
 	fn deref(&self) -> &Self::Target {
-		self.get_inner()
+		match self {
+			Trigger::Command(s) | Trigger::Redeem(s) => s,
+			// chat commands can't start with an @, so these never clash with them
+			Trigger::Follow => "@follow",
+			Trigger::Subscribe => "@subscribe",
+			Trigger::Resub => "@resub",
+			Trigger::GiftSub => "@giftsub",
+			Trigger::Cheer => "@cheer",
+			Trigger::Raid => "@raid",
+			Trigger::StreamStart => "@streamstart",
+			Trigger::StreamEnd => "@streamend",
+		}
+	}
+}
+
+/// Everything an action knows about what triggered it
+#[derive(Debug, Default, Clone)]
+pub struct ExecCtx {
+	pub user: Box<str>,
+	pub prompt: Option<Box<str>>,
+	/// Extra template values, e.g. `viewers` for `{viewers}` in a raid message
+	pub vars: Vec<(&'static str, String)>,
+	/// Only record what would happen, without talking to twitch or changing anything
+	pub dry_run: bool,
+	/// Triggered by the simulator, replies go to the transcript and nothing is sent to twitch
+	pub simulated: bool,
+	/// What the exec tree did, shared between the clones of the context
+	pub trace: Arc<Mutex<Vec<String>>>,
+}
+
+impl ExecCtx {
+	pub fn new(user: &str, prompt: Option<&str>) -> Self {
+		Self {
+			user: Box::from(user),
+			prompt: prompt.map(Box::from),
+			vars: Vec::new(),
+			dry_run: false,
+			simulated: false,
+			trace: Arc::default(),
+		}
+	}
+
+	pub fn dry_run(mut self) -> Self {
+		self.dry_run = true;
+		self
+	}
+
+	pub fn simulated(mut self) -> Self {
+		self.simulated = true;
+		self
+	}
+
+	fn record(&self, line: String) {
+		tracing::debug!("exec: {line}");
+		self.trace.lock().push(line);
+	}
+
+	/// Everything that happened so far
+	pub fn trace(&self) -> Vec<String> {
+		self.trace.lock().clone()
+	}
+
+	pub fn with_var(mut self, name: &'static str, value: impl ToString) -> Self {
+		self.vars.push((name, value.to_string()));
+		self
+	}
+
+	/// Replaces `{user}`, `{prompt}`, `{uptime}` and all extra vars in the template
+	pub fn fill_template(&self, template: &str) -> String {
+		let mut s = template
+			.replace("{user}", &self.user)
+			.replace("{prompt}", self.prompt.as_deref().unwrap_or_default());
+
+		for (name, value) in &self.vars {
+			s = s.replace(&format!("{{{name}}}"), value);
+		}
+
+		// vars come first, the stream end passes the uptime of the stream that just ended
+		if s.contains("{uptime}") {
+			let uptime = stream::uptime().unwrap_or_else(|| String::from("offline"));
+			s = s.replace("{uptime}", &uptime);
+		}
+
+		s
 	}
 }
 
@@ -106,6 +309,18 @@ pub enum ExecTarget {
 	Other,
 }
 
+/// What a successful exec did, for a chance it's the branch that ran
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum ExecOutcome {
+	Said(String),
+	/// With the timeout in seconds
+	TimedOut(BanResult, u32),
+	Banned(BanResult),
+	Spoke(String),
+	/// Nothing was done, see the trace for what would have happened
+	DryRun,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub enum Exec {
 	ChatMsg(ArcStr),
@@ -117,52 +332,102 @@ pub enum Exec {
 }
 
 impl Exec {
-	pub async fn exec(&mut self, user: &str, prompt: Option<&str>) -> Option<()> {
-		let tw_client = TWITCH_CLIENT.read().await;
-		let username = match tw_client.get_username() {
-			None => {
-				tracing::error!("Username gone");
-				return None;
+	/// All counters in this exec tree
+	fn counters(&self) -> Vec<TwitchCounter> {
+		match self {
+			Exec::Counter(counter) => vec![counter.clone()],
+			Exec::Chance(_, opt1, opt2) => {
+				let mut counters = opt1.counters();
+				counters.extend(opt2.counters());
+				counters
 			}
-			Some(u) => u,
-		};
-		let client = tw_client.chat_client.clone();
+			_ => Vec::new(),
+		}
+	}
 
+	/// Names of the files in the asset folder this exec tree needs, none of the execs use one yet
+	pub fn assets(&self) -> Vec<ArcStr> {
 		match self {
-			Exec::ChatMsg(msg) => match client {
-				None => {
-					tracing::error!("Chat client not set up");
-					None
-				}
-				Some(client) => {
-					if let Err(e) = client
-						.say(username, process_reply(msg.as_ref()).to_string())
-						.await
-					{
-						tracing::error!("Couldn't send chat msg: {e}");
-						None
-					} else {
-						Some(())
-					}
+			Exec::Chance(_, opt1, opt2) => {
+				let mut assets = opt1.assets();
+				assets.extend(opt2.assets());
+				assets
+			}
+			_ => Vec::new(),
+		}
+	}
+
+	pub async fn exec(&mut self, ctx: &ExecCtx) -> Result<ExecOutcome, Error> {
+		let user = ctx.user.as_ref();
+		let prompt = ctx.prompt.as_deref();
+
+		let tw_client = TWITCH_CLIENT.read().await;
+
+		match self {
+			Exec::ChatMsg(msg) => {
+				drop(tw_client); // say needs the lock itself
+				let reply = ctx.fill_template(&process_reply(msg.as_ref()));
+				if ctx.dry_run {
+					ctx.record(format!("Would say: {reply}"));
+					return Ok(ExecOutcome::DryRun);
 				}
-			},
+
+				chat::say(reply.clone(), ctx.simulated).await?;
+				ctx.record(format!("Said: {reply}"));
+				Ok(ExecOutcome::Said(reply))
+			}
 			Exec::Timeout(target, timeout) => {
-				let target_user = match target {
-					ExecTarget::None => return None,
-					ExecTarget::User => user,
-					ExecTarget::Other => prompt?,
+				let target_user = match (target, prompt) {
+					(ExecTarget::User, _) => user,
+					(ExecTarget::Other, Some(prompt)) => prompt,
+					(ExecTarget::Other, None) => {
+						ctx.record(String::from("Timeout without a user to time out"));
+						return Err(Error::new(ErrorMsg::MissingPrompt));
+					}
+					(ExecTarget::None, _) => {
+						ctx.record(String::from("Timeout without a target"));
+						return Err(Error::new(ErrorMsg::MissingTarget));
+					}
 				};
 
-				tw_client.ban_user(target_user, "", Some(*timeout)).await
+				if ctx.dry_run {
+					ctx.record(format!("Would timeout {target_user} for {timeout}s"));
+					return Ok(ExecOutcome::DryRun);
+				}
+
+				let ban = tw_client
+					.ban_user(target_user, "", Some(*timeout), ctx.simulated)
+					.await?;
+				match ban.end_time.as_deref() {
+					None => ctx.record(format!("Timed out {target_user} for {timeout}s")),
+					Some(end) => ctx.record(format!("Timed out {target_user} until {end}")),
+				}
+				Ok(ExecOutcome::TimedOut(ban, *timeout))
 			}
 			Exec::Ban(target) => {
-				let target_user = match target {
-					ExecTarget::None => return None,
-					ExecTarget::User => user,
-					ExecTarget::Other => prompt?,
+				let target_user = match (target, prompt) {
+					(ExecTarget::User, _) => user,
+					(ExecTarget::Other, Some(prompt)) => prompt,
+					(ExecTarget::Other, None) => {
+						ctx.record(String::from("Ban without a user to ban"));
+						return Err(Error::new(ErrorMsg::MissingPrompt));
+					}
+					(ExecTarget::None, _) => {
+						ctx.record(String::from("Ban without a target"));
+						return Err(Error::new(ErrorMsg::MissingTarget));
+					}
 				};
 
-				tw_client.ban_user(target_user, "", None).await
+				if ctx.dry_run {
+					ctx.record(format!("Would ban {target_user}"));
+					return Ok(ExecOutcome::DryRun);
+				}
+
+				let ban = tw_client
+					.ban_user(target_user, "", None, ctx.simulated)
+					.await?;
+				ctx.record(format!("Banned {target_user}"));
+				Ok(ExecOutcome::Banned(ban))
 			}
 			Exec::Chance(chance, opt1, opt2) => {
 				drop(tw_client); // freeing the lock is required here
@@ -173,14 +438,23 @@ impl Exec {
 					random_f = rng.random_range(0.0..1.0);
 				}
 				if random_f < *chance {
-					Box::pin(opt1.exec(user, prompt)).await
+					ctx.record(format!(
+						"Rolled {random_f:.2} < {chance}, taking the first option"
+					));
+					Box::pin(opt1.exec(ctx)).await
 				} else {
-					Box::pin(opt2.exec(user, prompt)).await
+					ctx.record(format!(
+						"Rolled {random_f:.2} >= {chance}, taking the second option"
+					));
+					Box::pin(opt2.exec(ctx)).await
 				}
 			}
 			Exec::Counter(counter) => {
-				let resp: String = match prompt {
-					Some("reset") => counter.reset().await,
+				drop(tw_client); // say needs the lock itself
+
+				// None resets the counter
+				let to_add: Option<u32> = match prompt {
+					Some("reset") => None,
 					Some(text) => {
 						let mut splitter = text.split(' ');
 						let cmd = splitter.next();
@@ -190,46 +464,52 @@ impl Exec {
 							&& let Some(num) = num
 							&& cmd == "add" && let Ok(num) = num.parse()
 						{
-							counter.add(num).await
+							Some(num)
 						} else {
-							counter.add(1).await
+							Some(1)
 						}
 					}
-					_ => counter.add(1).await,
+					_ => Some(1),
 				};
 
-				match client {
-					None => {
-						tracing::error!("Chat client not set up");
-						None
-					}
-					Some(client) => {
-						if let Err(e) = client.say(username, resp).await {
-							tracing::error!("Couldn't send chat msg: {e}");
-							None
-						} else {
-							Some(())
-						}
-					}
+				if ctx.dry_run {
+					ctx.record(format!(
+						"Would say: {}",
+						counter.preview(to_add, ctx.simulated)
+					));
+					return Ok(ExecOutcome::DryRun);
 				}
+
+				let resp: String = match to_add {
+					None => counter.reset(ctx.simulated).await,
+					Some(num) => counter.add(num, ctx.simulated).await,
+				};
+
+				chat::say(resp.clone(), ctx.simulated).await?;
+				ctx.record(format!("Said: {resp}"));
+				Ok(ExecOutcome::Said(resp))
 			}
 			Exec::Tts(specific_message) => {
 				tracing::debug!("prompt: {prompt:?}");
 
 				let message: String;
 				if !specific_message.is_empty() {
-					message = specific_message.replace("{user}", user);
+					message = ctx.fill_template(specific_message);
 				} else if let Some(prompt) = prompt {
 					message = format!("{user} said {prompt}");
 				} else {
-					tracing::warn!("TTS without any text?");
-					return None;
+					ctx.record(String::from("TTS without any text"));
+					return Err(Error::new(ErrorMsg::MissingPrompt));
 				}
-				if let Err(e) = tts::speak(message, None) {
-					tracing::error!("Error executing tts action: {e}");
-					return None;
-				};
-				Some(())
+
+				if ctx.dry_run {
+					ctx.record(format!("Would speak: {message}"));
+					return Ok(ExecOutcome::DryRun);
+				}
+
+				tts::speak(message.clone(), None)?;
+				ctx.record(format!("Spoke: {message}"));
+				Ok(ExecOutcome::Spoke(message))
 			}
 		}
 	}
@@ -251,22 +531,100 @@ fn process_reply(s: &str) -> Cow<'_, str> {
 	})
 }
 
+/// What happens to a channel point redemption after its action ran
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum RedeemPolicy {
+	/// Leave it in the reward queue for the streamer
+	#[default]
+	Manual,
+	/// Fulfill it if the action succeeded, leave failed ones for review
+	AutoFulfill,
+	/// Fulfill it if the action succeeded, refund the points if it failed
+	RefundOnFailure,
+	/// Hold it in the bot's review queue, the action only runs once approved.
+	/// Rewards that skip the request queue are held too, but rejecting can't refund those.
+	Review,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct Action {
 	pub trigger: Trigger,
 	pub exec: Exec,
 	#[serde(default)]
 	pub disabled: bool,
+	#[serde(default)]
+	pub redeem_policy: RedeemPolicy,
+	/// Title of the linked reward, remembered so the action can find it again if the reward gets re-created
+	#[serde(default)]
+	pub reward_title: Option<Box<str>>,
+	/// Also run for any reward with the same title, not just the linked one
+	#[serde(default)]
+	pub match_title: bool,
+	/// Groups the action belongs to, e.g. `games` or `moderation`, so they can be enabled or disabled together
+	#[serde(default)]
+	pub tags: Vec<Box<str>>,
+	/// Format version of the file, missing in files from before versioning
+	#[serde(default)]
+	pub version: u32,
 	#[serde(skip)]
 	pub last_used: Arc<AtomicU64>,
 }
 
 impl Action {
-	pub fn allow_use(&self) -> bool {
+	/// Runs the exec tree and records the run in the history
+	pub async fn run(&mut self, ctx: &ExecCtx) -> Result<ExecOutcome, Error> {
+		let res = self.exec.exec(ctx).await;
+
+		let (status, error) = match &res {
+			Ok(_) => (ExecStatus::Success, None),
+			Err(e) => {
+				tracing::warn!("Action {} failed: {e}", self.trigger.deref());
+				(ExecStatus::Failed, Some(e.to_string()))
+			}
+		};
+
+		history::record(HistoryEntry::new(
+			&self.trigger,
+			&ctx.user,
+			ctx.prompt.as_deref(),
+			status,
+			error,
+			ctx.trace(),
+			ctx.simulated,
+		));
+
+		res
+	}
+
+	/// Tags are case insensitive
+	pub fn has_tag(&self, tag: &str) -> bool {
+		self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+	}
+
+	/// Records that the action was triggered, but didn't run
+	pub fn skip(&self, ctx: &ExecCtx, reason: &str) {
+		history::record(HistoryEntry::new(
+			&self.trigger,
+			&ctx.user,
+			ctx.prompt.as_deref(),
+			ExecStatus::Skipped,
+			Some(reason.to_string()),
+			Vec::new(),
+			ctx.simulated,
+		));
+	}
+
+	/// Simulated uses only check if the action is enabled.
+	/// The cooldown is shared with the live action, so simulating would block real chatters.
+	pub fn allow_use(&self, simulated: bool) -> bool {
 		if self.disabled {
 			return false;
 		};
 
+		if simulated {
+			return true;
+		}
+
 		let now = SystemTime::now();
 		let since = now
 			.duration_since(UNIX_EPOCH)
@@ -322,11 +680,73 @@ pub async fn get_action(key: &str) -> Option<Action> {
 	Some(a.value().clone())
 }
 
-pub async fn add_action(action: Action) {
-	if let Err(e) = save_action(&action) {
-		tracing::error!("Error saving actions: {e}")
+/// Finds the action of a reward by its id.
+/// Falls back to the title, for actions that allow it.
+pub async fn get_redeem_action(reward_id: &str, title: &str) -> Option<Action> {
+	if let Some(action) = get_action(reward_id).await
+		&& matches!(action.trigger, Trigger::Redeem(_))
+	{
+		return Some(action);
+	}
+
+	ACTION_TABLE
+		.iter()
+		.find(|inner| {
+			let action = inner.value();
+			matches!(action.trigger, Trigger::Redeem(_))
+				&& action.match_title
+				&& action
+					.reward_title
+					.as_deref()
+					.is_some_and(|t| t.eq_ignore_ascii_case(title))
+		})
+		.map(|inner| inner.value().clone())
+}
+
+/// Remembers the title of the reward linked to an action, if it changed
+pub fn set_reward_title(key: &str, title: &str) {
+	let mut action = match ACTION_TABLE.get_mut(key) {
+		None => return,
+		Some(a) => a,
+	};
+
+	if action.reward_title.as_deref() == Some(title) {
+		return;
+	}
+
+	action.value_mut().reward_title = Some(Box::from(title));
+	if let Err(e) = save_action(action.value()) {
+		tracing::warn!("Error saving reward title: {e}")
+	};
+}
+
+/// Moves an action to a new trigger, e.g. to a re-created reward
+pub async fn relink_action(old_key: &str, trigger: Trigger) -> Option<()> {
+	let (_, mut action) = ACTION_TABLE.remove(old_key)?;
+
+	tracing::info!("Relinking action {old_key} to {}", trigger.deref());
+	let old_trigger = std::mem::replace(&mut action.trigger, trigger);
+	if let Err(e) = add_action(action.clone()).await {
+		// the old file is still there, so keep using it
+		tracing::error!("Couldn't save the relinked action, keeping {old_key}: {e}");
+		action.trigger = old_trigger;
+		_ = ACTION_TABLE.insert(action.trigger.key(), action);
+		return None;
 	}
-	_ = ACTION_TABLE.insert(action.trigger.get_inner().clone(), action);
+
+	if let Err(e) = delete_action_from_fs(old_key, false) {
+		tracing::error!("Error deleting action from fs: {e}")
+	};
+
+	Some(())
+}
+
+/// Saves the action and adds it to the table, nothing is added if it couldn't be saved
+pub async fn add_action(mut action: Action) -> Result<(), Error> {
+	action.version = ACTION_VERSION;
+	save_action(&action)?;
+	_ = ACTION_TABLE.insert(action.trigger.key(), action);
+	Ok(())
 }
 
 pub async fn drop_action(key: &str) {
@@ -335,14 +755,102 @@ pub async fn drop_action(key: &str) {
 		None => tracing::warn!("Action {key} was not found, nothing removed"),
 	}
 
-	if let Err(e) = delete_action_from_fs(key) {
+	// deleting from the UI can be a misclick, so it can be restored from the trash
+	if let Err(e) = delete_action_from_fs(key, true) {
 		tracing::error!("Error deleting action from fs: {e}")
 	};
 }
 
-pub async fn get_all_actions() -> Vec<Action> {
+/// What re-reading an action file changed in the table
+pub enum FileReload {
+	Unchanged,
+	Updated(ArcStr),
+	Removed(ArcStr),
+	/// The file of a loaded action broke, so the action was unloaded until the file is fixed
+	Broken(ArcStr, ActionLoadError),
+}
+
+fn key_of_file(name: &str) -> Option<ArcStr> {
+	ACTION_FILES
+		.iter()
+		.find(|inner| inner.value().as_ref() == name)
+		.map(|inner| inner.key().clone())
+}
+
+/// Syncs the action table with a file that was changed on disk
+pub fn reload_action_file(path: &Path) -> Result<FileReload, ActionLoadError> {
+	let name = file_name(path);
+
+	if !path.is_file() {
+		remove_file_report(path);
+
+		let key = match key_of_file(name.as_str()) {
+			None => return Ok(FileReload::Unchanged),
+			Some(k) => k,
+		};
+		ACTION_FILES.remove(&key);
+
+		return match ACTION_TABLE.remove(&key) {
+			None => Ok(FileReload::Unchanged),
+			Some((key, _)) => Ok(FileReload::Removed(key)),
+		};
+	}
+
+	let content = fs::read_to_string(path)?;
+	let (mut action, path) = match parse_action(content.as_str()) {
+		Err(e) => {
+			set_file_report(path, None, Some(e.clone()), None);
+
+			// keeping the old version would overwrite the broken edit on its next save
+			let key = match key_of_file(name.as_str()) {
+				None => return Err(e),
+				Some(k) => k,
+			};
+			ACTION_FILES.remove(&key);
+			ACTION_TABLE.remove(&key);
+
+			return Ok(FileReload::Broken(key, e));
+		}
+		Ok((action, migrated_from)) => {
+			// the trigger was changed in the file
+			if let Some(old_key) = key_of_file(name.as_str())
+				&& old_key != action.trigger.key()
+			{
+				ACTION_FILES.remove(&old_key);
+				ACTION_TABLE.remove(&old_key);
+			}
+
+			let path = register_file(&ACTION_FILES, path, &action);
+			if let Some(from) = migrated_from
+				&& let Err(e) = rewrite_migrated(&path, &action, from)
+			{
+				tracing::warn!("Couldn't rewrite migrated action file {path:?}: {e}");
+			}
+			(action, path)
+		}
+	};
+	let key = action.trigger.key();
+	set_file_report(&path, Some(key.deref()), None, None);
+
+	if let Some(existing) = ACTION_TABLE.get(&key) {
+		// our own saves end up here too
+		if toml::to_string_pretty(existing.value()).is_ok_and(|s| s == content) {
+			return Ok(FileReload::Unchanged);
+		}
+
+		// an edit shouldn't reset the cooldown
+		action.last_used = existing.last_used.clone();
+	}
+
+	_ = ACTION_TABLE.insert(key.clone(), action);
+	Ok(FileReload::Updated(key))
+}
+
+/// All actions, or only the ones with the tag
+pub async fn get_all_actions(tag: Option<&str>) -> Vec<Action> {
 	let mut v: Vec<Action> = ACTION_TABLE
 		.iter()
+		.filter(|inner| tag.is_none_or(|tag| inner.value().has_tag(tag)))
 		.map(|inner| inner.value().clone())
 		.collect();
 	v.sort_unstable();
@@ -361,6 +869,81 @@ pub fn toggle_disable_action(key: &str) -> Option<bool> {
 	Some(res)
 }
 
+/// Every tag in use, sorted
+pub fn get_tags() -> Vec<String> {
+	let mut tags: Vec<String> = ACTION_TABLE
+		.iter()
+		.flat_map(|inner| {
+			inner
+				.value()
+				.tags
+				.iter()
+				.map(|t| t.to_lowercase())
+				.collect::<Vec<_>>()
+		})
+		.collect();
+	tags.sort_unstable();
+	tags.dedup();
+	tags
+}
+
+/// Enables or disables every action with the tag, returns how many there are
+pub fn set_tag_disabled(tag: &str, disabled: bool) -> usize {
+	let mut count = 0;
+
+	for mut inner in ACTION_TABLE.iter_mut() {
+		if !inner.value().has_tag(tag) {
+			continue;
+		}
+		count += 1;
+
+		if inner.value().disabled == disabled {
+			continue;
+		}
+
+		inner.value_mut().disabled = disabled;
+		if let Err(e) = save_action(inner.value()) {
+			tracing::warn!("Error saving action as disabled: {e}")
+		};
+	}
+
+	tracing::info!("Set {count} actions tagged {tag} to disabled: {disabled}");
+	count
+}
+
+/// Resets all counters that only count a single stream
+pub async fn reset_stream_counters() {
+	let counters: Vec<TwitchCounter> = ACTION_TABLE
+		.iter()
+		.flat_map(|inner| inner.value().exec.counters())
+		.filter(|counter| counter.resets_per_stream())
+		.collect();
+
+	if counters.is_empty() {
+		return;
+	}
+
+	for mut counter in counters {
+		counter.reset(false).await;
+	}
+
+	if let Err(e) = save_actions().await {
+		tracing::warn!("Couldn't save reset counters: {e}");
+	}
+}
+
+/// Forgets what the simulation counted, the real counts are left as they were
+pub fn drop_simulated_counters() {
+	for inner in ACTION_TABLE.iter() {
+		inner
+			.value()
+			.exec
+			.counters()
+			.iter()
+			.for_each(TwitchCounter::drop_simulated);
+	}
+}
+
 pub async fn save_actions() -> Result<(), Error> {
 	create_dir_all(ACTION_DIR.as_path())?;
 
@@ -376,10 +959,80 @@ pub async fn save_actions() -> Result<(), Error> {
 	Ok(())
 }
 
+/// Lowercase ascii letters and digits, everything else becomes a single dash
+fn slug(s: &str) -> String {
+	let mut slug = String::new();
+	for c in s.chars() {
+		if slug.len() >= 32 {
+			break;
+		}
+
+		if c.is_ascii_alphanumeric() {
+			slug.push(c.to_ascii_lowercase());
+		} else if !slug.is_empty() && !slug.ends_with('-') {
+			slug.push('-');
+		}
+	}
+
+	match slug.trim_end_matches('-') {
+		"" => String::from("action"),
+		s => s.to_string(),
+	}
+}
+
+/// A readable slug plus a uuid, so two triggers can never share a file,
+/// not even on case insensitive file systems
+fn new_file_name(action: &Action) -> Box<str> {
+	// reward ids aren't readable, the title is
+	let readable = match (&action.trigger, action.reward_title.as_deref()) {
+		(Trigger::Redeem(_), Some(title)) => title,
+		(trigger, _) => trigger.deref(),
+	};
+
+	format!("{}-{}.toml", slug(readable), Uuid::new_v4().simple()).into()
+}
+
+fn is_generated_name(name: &str) -> bool {
+	name.strip_suffix(".toml")
+		.and_then(|stem| stem.rsplit_once('-'))
+		.is_some_and(|(_, id)| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Remembers in `files` which file belongs to the action.
+/// Files named after the trigger, from older versions, get a generated name.
+fn register_file(files: &DashMap<ArcStr, Box<str>>, path: &Path, action: &Action) -> PathBuf {
+	let key = action.trigger.key();
+	let name = file_name(path);
+
+	if is_generated_name(name.as_str()) {
+		files.insert(key, name.into());
+		return path.to_path_buf();
+	}
+
+	let new_name = new_file_name(action);
+	let new_path = path.with_file_name(new_name.as_ref());
+	match fs::rename(path, &new_path) {
+		Err(e) => {
+			tracing::warn!("Couldn't rename action file {path:?}: {e}");
+			files.insert(key, name.into());
+			path.to_path_buf()
+		}
+		Ok(()) => {
+			tracing::info!("Renamed action file {path:?} to {new_name}");
+			files.insert(key, new_name);
+			new_path
+		}
+	}
+}
+
 fn save_action(action: &Action) -> Result<(), Error> {
 	create_dir_all(ACTION_DIR.as_path())?;
 
-	let p = ACTION_DIR.join(format!("{}.toml", action.trigger.deref()));
+	let name = ACTION_FILES
+		.entry(action.trigger.key())
+		.or_insert_with(|| new_file_name(action))
+		.clone();
+	let p = ACTION_DIR.join(name.as_ref());
 
 	tracing::info!("action path {p:?}");
 
@@ -395,43 +1048,174 @@ fn save_action(action: &Action) -> Result<(), Error> {
 	Ok(())
 }
 
-fn delete_action_from_fs(key: &str) -> Result<(), Error> {
-	let target_name = format!("{key}.toml");
-	tracing::debug!("Trying to delete {target_name}");
-
-	let count = read_dir(ACTION_DIR.as_path())?
-		.filter_map(Result::ok)
-		.map(|entry| entry.path())
-		.filter(|path| {
-			path.is_file()
-				&& path
-					.file_name()
-					.and_then(|n| n.to_str())
-					.map(|n| n.eq_ignore_ascii_case(&target_name))
-					.unwrap_or(false)
-		})
-		.inspect(|path| {
-			tracing::debug!("Found {target_name}, removing...");
-			if let Err(e) = remove_file(path) {
-				tracing::error!("Error deleting action from fs: {e}")
-			}
-		})
-		.count();
+fn delete_action_from_fs(key: &str, to_trash: bool) -> Result<(), Error> {
+	let target_name = match ACTION_FILES.remove(key) {
+		None => {
+			tracing::warn!("Action {key} has no file, nothing deleted");
+			return Ok(());
+		}
+		Some((_, name)) => name,
+	};
+	tracing::debug!("Deleting {target_name}");
 
-	if count == 0 {
+	let path = ACTION_DIR.join(target_name.as_ref());
+	if !path.is_file() {
 		tracing::warn!(
 			"File `{target_name}` not found in {:?}",
 			ACTION_DIR.as_path()
 		);
+		return Ok(());
 	}
 
+	if to_trash {
+		return trash::move_to_trash(&path);
+	}
+
+	remove_file(path)?;
 	Ok(())
 }
 
+/// How loading each action file went, broken files included
+pub fn get_load_report() -> Vec<ActionFileReport> {
+	LazyLock::force(&ACTION_TABLE);
+	LOAD_REPORT.read().clone()
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default()
+}
+
+fn set_file_report(
+	path: &Path,
+	key: Option<&str>,
+	error: Option<ActionLoadError>,
+	quarantined: Option<String>,
+) {
+	let report = ActionFileReport {
+		file: file_name(path),
+		key: key.map(str::to_string),
+		error,
+		quarantined,
+	};
+
+	let mut lock = LOAD_REPORT.write();
+	match lock.iter_mut().find(|r| r.file == report.file) {
+		None => lock.push(report),
+		Some(r) => *r = report,
+	}
+}
+
+fn remove_file_report(path: &Path) {
+	let file = file_name(path);
+	LOAD_REPORT.write().retain(|r| r.file != file);
+}
+
+/// Parses an action file, migrating it if it is older.
+/// Also returns the version it was migrated from.
+fn parse_action(content: &str) -> Result<(Action, Option<u32>), ActionLoadError> {
+	let table = content
+		.parse::<toml::Table>()
+		.map_err(|e| ActionLoadError::from_toml(&e, content))?;
+
+	if table_version(&table)? == ACTION_VERSION {
+		// parsing the text directly keeps the error positions
+		let action = toml::from_str::<Action>(content)
+			.map_err(|e| ActionLoadError::from_toml(&e, content))?;
+		return Ok((action, None));
+	}
+
+	migrate_action(table).map_err(|e| {
+		// the migrated table has no positions, the original text does if it is broken the same way
+		match toml::from_str::<Action>(content) {
+			Err(original) => ActionLoadError::from_toml(&original, content),
+			Ok(_) => e,
+		}
+	})
+}
+
+fn table_version(table: &toml::Table) -> Result<u32, ActionLoadError> {
+	let version = match table.get("version") {
+		None => 0,
+		Some(v) => match v.as_integer().and_then(|v| u32::try_from(v).ok()) {
+			None => return Err(ActionLoadError::from(String::from("Invalid version"))),
+			Some(v) => v,
+		},
+	};
+
+	if version > ACTION_VERSION {
+		return Err(ActionLoadError {
+			newer_version: true,
+			..ActionLoadError::from(format!(
+				"Made by a newer version of {NAME_CAPITALIZED} (file version {version}, supported {ACTION_VERSION})"
+			))
+		});
+	}
+
+	Ok(version)
+}
+
+/// Upgrades an action in its TOML form to the current version.
+/// Also returns the version it was migrated from, if it was older.
+pub fn migrate_action(mut table: toml::Table) -> Result<(Action, Option<u32>), ActionLoadError> {
+	let version = table_version(&table)?;
+
+	for migration in MIGRATIONS.get(version as usize..).unwrap_or_default() {
+		migration(&mut table);
+	}
+	table.insert(String::from("version"), toml::Value::from(ACTION_VERSION));
+
+	let action = toml::Value::Table(table)
+		.try_into::<Action>()
+		.map_err(|e| ActionLoadError::from(e.to_string()))?;
+	Ok((action, (version < ACTION_VERSION).then_some(version)))
+}
+
+/// Backs up the original file, then rewrites it in the current format
+fn rewrite_migrated(path: &Path, action: &Action, from: u32) -> Result<(), Error> {
+	back_up(path, BACKUP_DIR.as_path(), from)?;
+	save_action(action)
+}
+
+/// Copies the file into the backup dir, earlier backups of the same name are kept
+fn back_up(path: &Path, dir: &Path, from: u32) -> Result<(), Error> {
+	create_dir_all(dir)?;
+
+	let stem = path
+		.file_stem()
+		.map(|s| s.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	let mut backup = dir.join(format!("{stem}.v{from}.toml"));
+	if backup.exists() {
+		backup = dir.join(format!("{stem}.v{from}.{}.toml", get_unix_milli()));
+	}
+
+	fs::copy(path, &backup)?;
+	tracing::info!("Migrating {path:?} from version {from}, backup at {backup:?}");
+	Ok(())
+}
+
+/// Moves a broken action file out of the way and returns where it went
+fn quarantine(path: &Path) -> Result<PathBuf, Error> {
+	create_dir_all(QUARANTINE_DIR.as_path())?;
+
+	let name = file_name(path);
+	let mut target = QUARANTINE_DIR.join(&name);
+	if target.exists() {
+		target = QUARANTINE_DIR.join(format!("{}-{name}", get_unix()));
+	}
+
+	fs::rename(path, &target)?;
+	Ok(target)
+}
+
 fn init_map() -> Result<DashMap<ArcStr, Action>, Error> {
 	let m = DashMap::new();
+	let mut report = Vec::new();
 
-	read_dir(ACTION_DIR.as_path())?
+	// collected first, old files get renamed while loading
+	let paths: Vec<PathBuf> = read_dir(ACTION_DIR.as_path())?
 		.filter_map(Result::ok)
 		.map(|entry| entry.path())
 		.filter(|path| {
@@ -442,14 +1226,261 @@ fn init_map() -> Result<DashMap<ArcStr, Action>, Error> {
 					.map(|extension| extension.eq_ignore_ascii_case("toml"))
 					.unwrap_or(false)
 		})
-		.filter_map(|path| {
-			let content = fs::read_to_string(&path).ok()?;
-			toml::from_str::<Action>(content.as_str()).ok()
-		})
-		.for_each(|action| {
-			let key = action.trigger.get_inner().clone();
-			m.insert(key, action);
-		});
+		.collect();
+
+	for path in paths {
+		let res = fs::read_to_string(&path)
+			.map_err(ActionLoadError::from)
+			.and_then(|content| parse_action(content.as_str()));
 
+		match res {
+			Ok((action, migrated_from)) => {
+				let path = register_file(&ACTION_FILES, &path, &action);
+				if let Some(from) = migrated_from
+					&& let Err(e) = rewrite_migrated(&path, &action, from)
+				{
+					tracing::warn!("Couldn't rewrite migrated action file {path:?}: {e}");
+				}
+
+				let key = action.trigger.key();
+				report.push(ActionFileReport {
+					file: file_name(&path),
+					key: Some(key.deref().to_string()),
+					error: None,
+					quarantined: None,
+				});
+				m.insert(key, action);
+			}
+			Err(e) if e.newer_version => {
+				// moving it would lose it when going back to the newer version
+				tracing::warn!("Skipping action file {path:?}: {e}");
+
+				report.push(ActionFileReport {
+					file: file_name(&path),
+					key: None,
+					error: Some(e),
+					quarantined: None,
+				});
+			}
+			Err(e) => {
+				tracing::warn!("Couldn't load action file {path:?}: {e}");
+
+				let quarantined = match quarantine(&path) {
+					Err(e) => {
+						tracing::error!("Couldn't quarantine {path:?}: {e}");
+						None
+					}
+					Ok(target) => {
+						tracing::info!("Moved {path:?} to {target:?}");
+						Some(target.display().to_string())
+					}
+				};
+
+				report.push(ActionFileReport {
+					file: file_name(&path),
+					key: None,
+					error: Some(e),
+					quarantined,
+				});
+			}
+		}
+	}
+
+	*LOAD_REPORT.write() = report;
 	Ok(m)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_error_position() {
+		// valid TOML, but not a valid action
+		let content = "trigger = { Command = \"!hi\" }\nexec = { ChatMsg = 5 }\n";
+		let e = parse_action(content).unwrap_err();
+		assert_eq!((e.line, e.column), (Some(2), Some(20)));
+		assert!(!e.newer_version);
+	}
+
+	#[test]
+	fn parse_error_column_counts_chars() {
+		// broken TOML after a multi-byte character
+		let content = "trigger = { Command = \"!hi\" }\ntags = [\"é\", tru]\n";
+		let e = parse_action(content).unwrap_err();
+		assert_eq!((e.line, e.column), (Some(2), Some(14)));
+		assert_eq!(e.to_string(), format!("{} at 2:14", e.message));
+	}
+
+	#[test]
+	fn parse_current_version() {
+		let content = "version = 1\ntrigger = { Command = \"!hi\" }\nexec = { ChatMsg = \"hi\" }\n";
+		let (action, migrated_from) = parse_action(content).unwrap();
+		assert_eq!(action.trigger, Trigger::Command(ArcStr::from("!hi")));
+		assert_eq!(migrated_from, None);
+	}
+
+	fn table(content: &str) -> toml::Table {
+		content.parse().unwrap()
+	}
+
+	// unique per test, they run in parallel
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("actions-test-{name}-{}", std::process::id()));
+		create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn table_versions() {
+		assert_eq!(table_version(&table("")).unwrap(), 0);
+		assert_eq!(table_version(&table("version = 1")).unwrap(), 1);
+		assert!(
+			!table_version(&table("version = -1"))
+				.unwrap_err()
+				.newer_version
+		);
+		assert!(
+			!table_version(&table("version = \"1\""))
+				.unwrap_err()
+				.newer_version
+		);
+
+		let newer = table(&format!("version = {}", ACTION_VERSION + 1));
+		assert!(table_version(&newer).unwrap_err().newer_version);
+	}
+
+	#[test]
+	fn migrate_unversioned() {
+		let (action, migrated_from) = migrate_action(table(
+			"trigger = { Command = \"!hi\" }\nexec = { ChatMsg = \"hi\" }",
+		))
+		.unwrap();
+		assert_eq!(migrated_from, Some(0));
+		assert_eq!(action.version, ACTION_VERSION);
+	}
+
+	#[test]
+	fn migrate_current_is_untouched() {
+		let content = format!(
+			"version = {ACTION_VERSION}\ntrigger = \"Follow\"\nexec = {{ ChatMsg = \"hi\" }}"
+		);
+		let (action, migrated_from) = migrate_action(table(&content)).unwrap();
+		assert_eq!(migrated_from, None);
+		assert_eq!(action.trigger, Trigger::Follow);
+	}
+
+	#[test]
+	fn newer_file_is_not_migrated() {
+		let content = format!(
+			"version = {}\ntrigger = \"Follow\"\nexec = {{ ChatMsg = \"hi\" }}",
+			ACTION_VERSION + 1
+		);
+		assert!(migrate_action(table(&content)).unwrap_err().newer_version);
+		assert!(parse_action(&content).unwrap_err().newer_version);
+	}
+
+	#[test]
+	fn backs_up_every_time() {
+		let dir = temp_dir("backup");
+		let path = dir.join("hi.toml");
+		let backups = dir.join("backups");
+		let backup = backups.join("hi.v0.toml");
+
+		fs::write(&path, "original").unwrap();
+		back_up(&path, &backups, 0).unwrap();
+		assert_eq!(fs::read_to_string(&backup).unwrap(), "original");
+
+		fs::write(&path, "edited").unwrap();
+		back_up(&path, &backups, 0).unwrap();
+		assert_eq!(fs::read_to_string(&backup).unwrap(), "original");
+
+		let contents: Vec<String> = read_dir(&backups)
+			.unwrap()
+			.map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+			.collect();
+		assert_eq!(contents.len(), 2);
+		assert!(contents.iter().any(|c| c == "edited"));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	fn command(name: &str) -> Action {
+		let content = format!("trigger = {{ Command = {name:?} }}\nexec = {{ ChatMsg = \"hi\" }}");
+		parse_action(&content).unwrap().0
+	}
+
+	#[test]
+	fn slugs() {
+		assert_eq!(slug("!Hello World"), "hello-world");
+		assert_eq!(slug("a--b__c"), "a-b-c");
+		assert_eq!(slug("../../etc/passwd"), "etc-passwd");
+		assert_eq!(slug("C:\\Windows\\system32"), "c-windows-system32");
+		assert_eq!(slug("..."), "action");
+		assert_eq!(slug("こんにちは"), "action");
+		assert_eq!(slug(&"a".repeat(100)).len(), 32);
+	}
+
+	#[test]
+	fn generated_names() {
+		let action = command("!hi");
+		assert!(is_generated_name(&new_file_name(&action)));
+
+		assert!(!is_generated_name("!hi.toml"));
+		assert!(!is_generated_name("hi-1234.toml"));
+		assert!(!is_generated_name(&format!(
+			"hi-{}.json",
+			Uuid::new_v4().simple()
+		)));
+		assert!(!is_generated_name(&format!("hi-{}.toml", "g".repeat(32))));
+	}
+
+	#[test]
+	fn unsafe_triggers_get_safe_file_names() {
+		for trigger in [
+			"!a/b", "!../../x", "!c:d", "CON", "nul", "Com1", "LPT1", "aux.txt",
+		] {
+			let name = new_file_name(&command(trigger));
+			assert!(is_generated_name(&name), "{name}");
+			assert!(
+				name.chars()
+					.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'),
+				"{name}"
+			);
+			// windows treats reserved names as devices, even with an extension, but not with the uuid after them
+			let stem = name.trim_end_matches(".toml");
+			assert!(
+				!["con", "nul", "com1", "lpt1", "aux"].contains(&stem),
+				"{name}"
+			);
+			assert!(!stem.contains('.'), "{name}");
+		}
+	}
+
+	#[test]
+	fn renames_legacy_files() {
+		let dir = temp_dir("legacy");
+		let action = command("!legacy-rename");
+		let legacy = dir.join("!legacy-rename.toml");
+		fs::write(&legacy, "legacy").unwrap();
+
+		let files = DashMap::new();
+		let path = register_file(&files, &legacy, &action);
+		assert!(!legacy.exists());
+		assert_eq!(path.parent(), Some(dir.as_path()));
+		assert_eq!(fs::read_to_string(&path).unwrap(), "legacy");
+
+		let name = file_name(&path);
+		assert!(name.starts_with("legacy-rename-"));
+		assert!(is_generated_name(&name));
+		assert_eq!(
+			files.get("!legacy-rename").as_deref().map(Box::as_ref),
+			Some(name.as_str())
+		);
+
+		// already renamed, stays as it is
+		assert_eq!(register_file(&files, &path, &action), path);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
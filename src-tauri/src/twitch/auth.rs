@@ -1,17 +1,58 @@
-use keyring::KeyringEntry;
+use keyring::{KeyringEntry, native};
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use std::{
 	fs::{self, File},
 	io::Write,
+	sync::Arc,
+	time::Duration,
+};
+use tauri_specta::Event;
+use tokio::{select, spawn, sync::Notify, time::sleep};
+use twitch_api::HelixClient;
+use twitch_oauth2::{
+	AccessToken, ClientId, ClientSecret, DeviceUserTokenBuilder, RefreshToken, Scope, TwitchToken,
+	UserToken,
 };
-use tokio::time::sleep;
-use twitch_oauth2::{AccessToken, ClientId, DeviceUserTokenBuilder, UserToken};
 
 use crate::{
 	config::{ARGS, Config},
 	error::{Error, ErrorMsg},
-	twitch::TwitchClient,
+	twitch::{
+		TWITCH_CLIENT, TwitchClient,
+		endpoints::EndpointClient,
+		scopes::upgraded_scopes,
+		token_store::{self, StoredToken},
+	},
+	utils::emit_event,
 };
 
+// refresh this long before the token expires
+const REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+/// Handle to the task refreshing the token before it expires.
+/// Dropping it stops the task.
+pub struct TokenRefresher {
+	shutdown: Arc<Notify>,
+}
+
+impl Drop for TokenRefresher {
+	fn drop(&mut self) {
+		self.shutdown.notify_one();
+	}
+}
+
+/// The login stopped working and the user has to log in again
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct AuthFailureEvent {
+	pub reason: String,
+}
+
+fn client_id() -> ClientId {
+	ClientId::new(env!("API_KEY").to_string())
+}
+
 impl TwitchClient {
 	pub async fn login(&self) -> Result<UserToken, ErrorMsg> {
 		let token = match load_token(self).await {
@@ -24,34 +65,107 @@ impl TwitchClient {
 }
 
 pub async fn twitch_auth(client: &TwitchClient) -> Result<UserToken, Error> {
-	let tkn = internal_twitch_auth(client)
+	// unwrap is safe here, default Config has scopes
+	let tkn = internal_twitch_auth(&client.client, Config::default().scopes.unwrap(), false)
 		.await
 		.map_err(|e| e.try_set_msg(ErrorMsg::TwitchAuth))?;
+
+	if let Err(e) = save_token(&tkn).await {
+		tracing::warn!("Failed to save token: {:#?}", e);
+	};
+
 	Ok(tkn)
 }
 
-async fn internal_twitch_auth(client: &TwitchClient) -> Result<UserToken, Error> {
-	let client_id = ClientId::new(env!("API_KEY").to_string());
+/// Logs in again, asking for the scopes the current token is missing on top of the ones it has.
+/// The client is only locked to get the token, the login can take minutes.
+pub async fn upgrade_scopes() -> Result<UserToken, Error> {
+	let (http, token) = {
+		let client = TWITCH_CLIENT.read().await;
+		(client.client.clone(), client.get_token())
+	};
 
-	// unwrap is safe here, default Config has scopes
-	let mut builder = DeviceUserTokenBuilder::new(client_id, Config::default().scopes.unwrap());
-	let code = builder.start(&client.client).await?;
+	let token = match token {
+		None => return Err(Error::new(ErrorMsg::TokenGone)),
+		Some(t) => t,
+	};
+
+	let tkn = internal_twitch_auth(&http, upgraded_scopes(&token), false)
+		.await
+		.map_err(|e| e.try_set_msg(ErrorMsg::TwitchAuth))?;
+
+	if let Err(e) = save_token(&tkn).await {
+		tracing::warn!("Failed to save token: {:#?}", e);
+	};
+
+	Ok(tkn)
+}
+
+/// Runs the device flow, the code is either shown in the browser or printed to the terminal
+async fn internal_twitch_auth(
+	http: &HelixClient<'static, EndpointClient>,
+	scopes: Vec<Scope>,
+	in_terminal: bool,
+) -> Result<UserToken, Error> {
+	let client_id = client_id();
+
+	let mut builder = DeviceUserTokenBuilder::new(client_id, scopes);
+	let code = builder.start(http).await?;
 
 	tracing::debug!("code: {:#?}", code);
 
 	let url = code.verification_uri.clone();
-	open::that(url)?;
+	if in_terminal {
+		println!("Open {url} and enter the code {}", code.user_code);
+		println!("Waiting for approval...");
+	} else if let Err(e) = open::that(url.as_str()) {
+		// without a browser the user can still open it on another device
+		tracing::warn!(
+			"Couldn't open the browser ({e}), open {url} and enter the code {}",
+			code.user_code
+		);
+	}
 
-	let token = builder.wait_for_code(&client.client, sleep).await?;
+	let token = builder.wait_for_code(http, sleep).await?;
+	Ok(token)
+}
 
-	if let Err(e) = save_token(&token).await {
-		tracing::warn!("Failed to save token: {:#?}", e);
-	};
+/// Logs in from the terminal, for machines without a browser or over SSH
+pub async fn cli_login() -> Result<(), Error> {
+	let http = TWITCH_CLIENT.read().await.client.clone();
 
-	Ok(token)
+	// unwrap is safe here, default Config has scopes
+	let token = internal_twitch_auth(&http, Config::default().scopes.unwrap(), true)
+		.await
+		.map_err(|e| e.try_set_msg(ErrorMsg::TwitchAuth))?;
+
+	match save_token(&token).await {
+		// no keyring and no passphrase yet, there is no frontend to ask for one
+		Err(e) if e.msg == ErrorMsg::PassphraseNeeded => {
+			token_store::set_passphrase(prompt_passphrase()?);
+			save_token(&token).await?;
+		}
+		res => res?,
+	}
+
+	println!("Logged in as {}", token.login);
+	Ok(())
 }
 
-async fn save_token(token: &UserToken) -> Result<(), Error> {
+/// Reads the passphrase for the encrypted token file from the terminal without echoing it,
+/// so it doesn't show up in the process list or the scrollback
+fn prompt_passphrase() -> Result<String, Error> {
+	let passphrase = rpassword::prompt_password(
+		"No keyring available, enter a passphrase to encrypt the token: ",
+	)?;
+
+	match passphrase.is_empty() {
+		true => Err(Error::new(ErrorMsg::PassphraseNeeded)),
+		false => Ok(passphrase),
+	}
+}
+
+pub async fn save_token(token: &UserToken) -> Result<(), Error> {
 	internal_save_token(token)
 		.await
 		.map_err(|e| e.try_set_msg(ErrorMsg::TokenSave))?;
@@ -65,23 +179,102 @@ async fn internal_save_token(token: &UserToken) -> Result<(), Error> {
 		return Err(Error::new(ErrorMsg::FeatureDisabled));
 	}
 
+	let refresh_token = token.refresh_token.as_ref().map(|t| t.as_str());
+
 	if let Some(file) = conf.token_file.clone() {
+		tracing::warn!("Saving the token in plain text to {file}, it includes the refresh token");
+		// access token on the first line, refresh token on the second
 		let mut f = File::create(file.as_ref())?;
 		f.write_all(token.access_token.as_str().as_bytes())?;
+		if let Some(refresh_token) = refresh_token {
+			f.write_all(b"\n")?;
+			f.write_all(refresh_token.as_bytes())?;
+		}
 		return Ok(());
 	}
 
+	match save_to_keyring(token.access_token.as_str(), refresh_token).await {
+		Ok(()) => {
+			// don't leave an older token lying around
+			if let Err(e) = token_store::delete() {
+				tracing::warn!("Couldn't delete encrypted token file: {e}");
+			}
+			Ok(())
+		}
+		Err(e) => {
+			tracing::warn!("Keyring unavailable, using the encrypted token file instead: {e}");
+			token_store::save(&StoredToken {
+				access_token: token.access_token.as_str().to_string(),
+				refresh_token: refresh_token.map(str::to_string),
+			})
+		}
+	}
+}
+
+async fn save_to_keyring(access_token: &str, refresh_token: Option<&str>) -> Result<(), Error> {
 	let entry = KeyringEntry::try_new("access_token")?;
-	entry.set_secret(token.access_token.as_str()).await?;
+	entry.set_secret(access_token).await?;
+
+	let entry = KeyringEntry::try_new("refresh_token")?;
+	match refresh_token {
+		Some(refresh_token) => entry.set_secret(refresh_token).await?,
+		None => _ = entry.delete_secret().await, // an old one would be useless
+	}
 
 	Ok(())
 }
 
-pub async fn forget_token() -> Result<(), Error> {
+async fn load_from_keyring() -> Result<(String, Option<String>), Error> {
 	let entry = KeyringEntry::try_new("access_token")?;
-	KeyringEntry::delete_secret(&entry).await?;
+	let access_token = entry.get_secret().await?;
 
-	Ok(())
+	let entry = KeyringEntry::try_new("refresh_token")?;
+	let refresh_token = entry.get_secret().await.ok();
+	Ok((access_token, refresh_token))
+}
+
+/// Nothing to delete counts as deleted, that includes having no keyring at all
+async fn delete_from_keyring(name: &str) -> Result<(), Error> {
+	let res = match KeyringEntry::try_new(name) {
+		Ok(entry) => entry.delete_secret().await,
+		Err(e) => Err(e),
+	};
+
+	match res {
+		Err(
+			e @ (keyring::Error::BuildEntryError(native::Error::PlatformFailure(_), _)
+			| keyring::Error::DeleteSecretError(
+				native::Error::NoEntry | native::Error::PlatformFailure(_),
+				_,
+			)),
+		) => {
+			tracing::debug!("No {name} in the keyring: {e}");
+			Ok(())
+		}
+		res => Ok(res?),
+	}
+}
+
+/// Removes the token from every store, even if one of them fails, and returns the first error
+pub async fn forget_token() -> Result<(), Error> {
+	let mut first_err = None;
+
+	if let Err(e) = token_store::delete() {
+		tracing::error!("Couldn't delete the encrypted token file: {e}");
+		first_err.get_or_insert(e);
+	}
+
+	for name in ["access_token", "refresh_token"] {
+		if let Err(e) = delete_from_keyring(name).await {
+			tracing::error!("Couldn't delete the {name} from the keyring: {e}");
+			first_err.get_or_insert(e);
+		}
+	}
+
+	match first_err {
+		None => Ok(()),
+		Some(e) => Err(e),
+	}
 }
 
 pub async fn load_token(client: &TwitchClient) -> Result<UserToken, Error> {
@@ -98,17 +291,120 @@ async fn internal_load_token(client: &TwitchClient) -> Result<UserToken, Error>
 		return Err(Error::new(ErrorMsg::FeatureDisabled));
 	}
 
-	if let Some(file) = conf.token_file.clone() {
-		let token_str = fs::read_to_string(file.as_ref())?;
+	let (access_token, refresh_token) = match conf.token_file.clone() {
+		Some(file) => {
+			let token_str = fs::read_to_string(file.as_ref())?;
+			let mut lines = token_str.lines();
+			let access_token = lines.next().unwrap_or_default().trim().to_string();
+			let refresh_token = lines
+				.next()
+				.map(|l| l.trim().to_string())
+				.filter(|l| !l.is_empty());
+			(access_token, refresh_token)
+		}
+		None => match load_from_keyring().await {
+			Ok(tokens) => tokens,
+			Err(e) if token_store::exists() => {
+				tracing::debug!("Nothing in the keyring, using the encrypted token file: {e}");
+				let stored = token_store::load()?;
+				(stored.access_token, stored.refresh_token)
+			}
+			Err(e) => return Err(e),
+		},
+	};
+
+	let access_token = AccessToken::new(access_token);
+	let refresh_token = refresh_token.map(RefreshToken::new);
+
+	let res = UserToken::from_existing(
+		&client.client,
+		access_token,
+		refresh_token.clone(),
+		None::<ClientSecret>,
+	)
+	.await;
+
+	match (res, refresh_token) {
+		(Ok(token), _) => Ok(token),
+		(Err(e), None) => Err(e.into()),
+		(Err(e), Some(refresh_token)) => {
+			// most likely expired while the bot wasn't running
+			tracing::info!("Saved token is invalid, refreshing it: {e}");
+
+			let token = UserToken::from_refresh_token(
+				&client.client,
+				refresh_token,
+				client_id(),
+				None::<ClientSecret>,
+			)
+			.await?;
+
+			if let Err(e) = save_token(&token).await {
+				tracing::warn!("Failed to save refreshed token: {e}");
+			}
 
-		return Ok(UserToken::from_token(&client.client, AccessToken::new(token_str)).await?);
+			Ok(token)
+		}
 	}
+}
 
-	let entry = KeyringEntry::try_new("access_token")?;
-	let access_token = entry.get_secret().await?;
-	let access_token = AccessToken::new(access_token);
+/// Starts refreshing the token shortly before it expires
+pub fn token_refresher(token: &UserToken) -> TokenRefresher {
+	let shutdown = Arc::new(Notify::new());
+	spawn(refresh_loop(token.clone(), shutdown.clone()));
+	TokenRefresher { shutdown }
+}
 
-	let token = UserToken::from_token(&client.client, access_token).await?;
+async fn refresh_loop(mut token: UserToken, shutdown: Arc<Notify>) {
+	loop {
+		let wait = token.expires_in().saturating_sub(REFRESH_MARGIN);
+		tracing::debug!("Refreshing token in {}s", wait.as_secs());
 
-	Ok(token)
+		select! {
+			biased;
+			_ = shutdown.notified() => return,
+			_ = sleep(wait) => {}
+		}
+
+		if token.refresh_token.is_none() {
+			tracing::error!("Token is about to expire and can't be refreshed");
+			emit_event(AuthFailureEvent {
+				reason: String::from("The login expired, please log in again"),
+			});
+			return;
+		}
+
+		let http = TWITCH_CLIENT.read().await.client.clone();
+		let mut refreshed = token.clone();
+		match refreshed.refresh_token(&http).await {
+			Ok(()) => {
+				tracing::info!("Refreshed token");
+				if let Err(e) = save_token(&refreshed).await {
+					tracing::warn!("Failed to save refreshed token: {e}");
+				}
+
+				TWITCH_CLIENT.write().await.update_token(refreshed.clone());
+				token = refreshed;
+			}
+			Err(e) if token.expires_in() > REFRESH_RETRY => {
+				tracing::warn!(
+					"Couldn't refresh token, retrying in {}s: {e}",
+					REFRESH_RETRY.as_secs()
+				);
+
+				select! {
+					biased;
+					_ = shutdown.notified() => return,
+					_ = sleep(REFRESH_RETRY) => {}
+				}
+			}
+			Err(e) => {
+				tracing::error!("Couldn't refresh token before it expired: {e}");
+				emit_event(AuthFailureEvent {
+					reason: format!("The login couldn't be refreshed, please log in again: {e}"),
+				});
+				return;
+			}
+		}
+	}
 }
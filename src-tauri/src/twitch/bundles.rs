@@ -0,0 +1,306 @@
+use std::{
+	collections::BTreeSet,
+	ffi::OsStr,
+	fs,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+	error::Error,
+	twitch::{
+		TWITCH_CLIENT,
+		actions::{Action, ArcStr, Trigger, add_action, get_action, migrate_action},
+	},
+	utils::{ASSET_DIR, get_unix},
+};
+
+// bump when the bundle layout changes, the actions inside have their own version
+const BUNDLE_VERSION: u32 = 1;
+
+/// Actions packed into one file to share them with other channels.
+/// Counters are part of their action, so they travel with it, including their count.
+#[derive(Debug, Serialize)]
+struct ActionBundle {
+	bundle_version: u32,
+	exported_at: u64,
+	actions: Vec<Action>,
+	// left out while none of the actions need a file
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	assets: Vec<BundleAsset>,
+}
+
+/// A file from the asset folder one of the actions needs
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleAsset {
+	name: Box<str>,
+	/// Base64 encoded file content
+	data: String,
+}
+
+/// What to do with an imported action if its trigger is already taken
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum ImportConflict {
+	/// Keep the existing action
+	#[default]
+	Skip,
+	/// Import commands under a free name, everything else is skipped
+	Rename,
+	/// Replace the existing action
+	Overwrite,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct ImportReport {
+	pub imported: Vec<String>,
+	pub skipped: Vec<String>,
+	/// Old and new command name
+	pub renamed: Vec<(String, String)>,
+	/// Redeems without a reward of the same title in this channel, they show up as orphaned actions
+	pub unmapped: Vec<String>,
+	/// Files written to the asset folder
+	pub assets: Vec<String>,
+	/// Files that already exist with a different content, the existing ones were kept
+	pub kept_assets: Vec<String>,
+	/// Actions or files in the bundle that couldn't be read
+	pub errors: Vec<String>,
+}
+
+/// Writes the actions to a bundle file, returns how many were exported
+pub async fn export_bundle(keys: &[String], path: &Path) -> Result<usize, Error> {
+	let client = TWITCH_CLIENT.read().await;
+	let rewards = client.get_redeems().unwrap_or_default();
+
+	let mut actions = Vec::with_capacity(keys.len());
+	for key in keys {
+		let mut action = match get_action(key).await {
+			None => {
+				tracing::warn!("Action {key} doesn't exist, not exporting it");
+				continue;
+			}
+			Some(a) => a,
+		};
+
+		// the reward id is useless elsewhere, the title is used to find the reward again
+		if let Trigger::Redeem(id) = &action.trigger
+			&& action.reward_title.is_none()
+			&& let Some(reward) = rewards.iter().find(|r| r.id.as_str() == id.deref())
+		{
+			action.reward_title = Some(Box::from(reward.title.as_str()));
+		}
+
+		actions.push(action);
+	}
+	drop(client);
+
+	let names: BTreeSet<ArcStr> = actions.iter().flat_map(|a| a.exec.assets()).collect();
+	let mut assets = Vec::with_capacity(names.len());
+	for name in names {
+		let data = match asset_path(&name).and_then(|p| Ok(fs::read(p)?)) {
+			Err(e) => {
+				tracing::warn!(
+					"Couldn't read asset {}, not exporting it: {e}",
+					name.deref()
+				);
+				continue;
+			}
+			Ok(d) => d,
+		};
+
+		assets.push(BundleAsset {
+			name: Box::from(name.deref()),
+			data: BASE64.encode(data),
+		});
+	}
+
+	let count = actions.len();
+	let bundle = ActionBundle {
+		bundle_version: BUNDLE_VERSION,
+		exported_at: get_unix(),
+		actions,
+		assets,
+	};
+
+	fs::write(path, toml::to_string_pretty(&bundle)?)?;
+	tracing::info!("Exported {count} actions to {path:?}");
+
+	Ok(count)
+}
+
+/// Reads a bundle file and adds its actions
+pub async fn import_bundle(path: &Path, conflict: ImportConflict) -> Result<ImportReport, Error> {
+	let content = fs::read_to_string(path)?;
+	let mut table = content.parse::<toml::Table>()?;
+
+	let version = table
+		.get("bundle_version")
+		.and_then(|v| v.as_integer())
+		.unwrap_or(0);
+	if version != i64::from(BUNDLE_VERSION) {
+		return Err(Error::from(format!("Unsupported bundle version {version}")));
+	}
+
+	let entries = match table.remove("actions") {
+		None => Vec::new(),
+		Some(toml::Value::Array(a)) => a,
+		Some(_) => return Err(Error::from("The actions of the bundle aren't a list")),
+	};
+
+	// only there if the exported actions needed any
+	let assets: Vec<BundleAsset> = match table.remove("assets") {
+		None => Vec::new(),
+		Some(assets) => assets.try_into()?,
+	};
+
+	let rewards = match TWITCH_CLIENT.read().await.update_redeems().await {
+		Err(e) => {
+			tracing::warn!("Couldn't get rewards, redeems won't be remapped: {e}");
+			Vec::new()
+		}
+		Ok(r) => r,
+	};
+
+	let mut report = ImportReport::default();
+	// files of the imported actions, others in the bundle aren't needed
+	let mut needed: BTreeSet<ArcStr> = BTreeSet::new();
+
+	for (i, entry) in entries.into_iter().enumerate() {
+		let res = match entry {
+			toml::Value::Table(t) => migrate_action(t),
+			_ => {
+				report.errors.push(format!("Action {i} isn't a table"));
+				continue;
+			}
+		};
+
+		let mut action = match res {
+			Err(e) => {
+				report.errors.push(format!("Action {i}: {e}"));
+				continue;
+			}
+			Ok((action, _)) => action,
+		};
+
+		if let Trigger::Redeem(id) = &action.trigger {
+			let reward = action.reward_title.as_deref().and_then(|title| {
+				rewards
+					.iter()
+					.find(|r| r.title.as_str().eq_ignore_ascii_case(title))
+			});
+
+			match reward {
+				None => report.unmapped.push(
+					action
+						.reward_title
+						.as_deref()
+						.unwrap_or(id.deref())
+						.to_string(),
+				),
+				Some(reward) => {
+					action.trigger = Trigger::Redeem(ArcStr::from(reward.id.as_str()));
+					action.reward_title = Some(Box::from(reward.title.as_str()));
+				}
+			}
+		}
+
+		let key = action.trigger.deref().to_string();
+		if get_action(&key).await.is_some() {
+			match (conflict, &action.trigger) {
+				(ImportConflict::Skip, _) => {
+					report.skipped.push(key);
+					continue;
+				}
+				(ImportConflict::Overwrite, _) => {}
+				(ImportConflict::Rename, Trigger::Command(name)) => {
+					let name = free_command_name(name).await;
+					report.renamed.push((key, name.clone()));
+					action.trigger = Trigger::Command(ArcStr::from(name.as_str()));
+				}
+				// only commands can be renamed
+				(ImportConflict::Rename, _) => {
+					report.skipped.push(key);
+					continue;
+				}
+			}
+		}
+
+		let key = action.trigger.deref().to_string();
+		let action_assets = action.exec.assets();
+		match add_action(action).await {
+			Err(e) => report.errors.push(format!("Couldn't save {key}: {e}")),
+			Ok(()) => {
+				report.imported.push(key);
+				needed.extend(action_assets);
+			}
+		}
+	}
+
+	for asset in assets {
+		if needed.contains(asset.name.as_ref()) {
+			restore_asset(asset, conflict, &mut report);
+		}
+	}
+
+	tracing::info!(
+		"Imported {} actions from {path:?}, skipped {}",
+		report.imported.len(),
+		report.skipped.len()
+	);
+
+	Ok(report)
+}
+
+/// Path of a file in the asset folder.
+/// Only plain file names are allowed, so a bundle can't write outside of the folder.
+fn asset_path(name: &str) -> Result<PathBuf, Error> {
+	if Path::new(name).file_name() != Some(OsStr::new(name)) {
+		return Err(Error::from(format!("Invalid asset name {name:?}")));
+	}
+
+	Ok(ASSET_DIR.join(name))
+}
+
+/// Writes a file of the bundle to the asset folder.
+/// An existing file with a different content is only replaced when overwriting.
+fn restore_asset(asset: BundleAsset, conflict: ImportConflict, report: &mut ImportReport) {
+	let name = asset.name.to_string();
+
+	let res = asset_path(&name).and_then(|path| {
+		let data = BASE64
+			.decode(asset.data.as_bytes())
+			.map_err(|e| Error::from(format!("Invalid asset data: {e}")))?;
+
+		match fs::read(&path) {
+			Ok(existing) if existing == data => return Ok(None),
+			Ok(_) if conflict != ImportConflict::Overwrite => return Ok(Some(false)),
+			_ => {}
+		}
+
+		fs::create_dir_all(ASSET_DIR.as_path())?;
+		fs::write(&path, data)?;
+		Ok(Some(true))
+	});
+
+	match res {
+		Err(e) => report.errors.push(format!("Asset {name}: {e}")),
+		Ok(None) => {} // already there
+		Ok(Some(true)) => report.assets.push(name),
+		Ok(Some(false)) => report.kept_assets.push(name),
+	}
+}
+
+/// The command name with the lowest free number appended
+async fn free_command_name(name: &str) -> String {
+	let mut n = 2;
+	loop {
+		let candidate = format!("{name}{n}");
+		if get_action(&candidate).await.is_none() {
+			return candidate;
+		}
+		n += 1;
+	}
+}
@@ -1,73 +1,346 @@
-use std::sync::{Arc, LazyLock};
+use std::{
+	convert::Infallible,
+	fmt,
+	sync::{
+		Arc, LazyLock,
+		atomic::{AtomicU64, Ordering},
+		nonpoison::RwLock,
+	},
+	time::Duration,
+};
 
-use indexmap::IndexMap;
+use async_trait::async_trait;
+use indexmap::{IndexMap, IndexSet};
 use rand::Rng;
-use tauri::async_runtime::{JoinHandle, spawn};
-use tokio::sync::Mutex;
-use twitch_irc::{ClientConfig, login::StaticLoginCredentials, message::ServerMessage};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::async_runtime::spawn;
+use tauri_specta::Event;
+use tokio::{
+	select,
+	sync::{Mutex, Notify},
+	time::{Instant, interval_at, sleep},
+};
+use twitch_irc::{
+	ClientConfig,
+	login::{CredentialsPair, LoginCredentials},
+	message::ServerMessage,
+};
+use twitch_oauth2::UserToken;
 
 use crate::{
 	error::{Error, ErrorMsg},
-	twitch::{IrcClient, TWITCH_CLIENT, TwitchClient, actions::get_action},
-	utils::{NAME_CAPITALIZED, get_unix},
+	twitch::{
+		IrcClient, TWITCH_CLIENT, action_groups,
+		actions::{ExecCtx, Trigger, get_action},
+		reward_groups, simulator,
+	},
+	utils::{NAME_CAPITALIZED, emit_event, get_unix},
 };
 
 static ACTIVE_CHATTERS: LazyLock<Mutex<IndexMap<Box<str>, u64>>> =
 	LazyLock::new(|| Mutex::new(IndexMap::new()));
 
-pub async fn chat_listener(twitch_client: &mut TwitchClient) -> Result<JoinHandle<()>, Error> {
-	tracing::debug!("Awaiting global twitch client read access");
+// everyone who chatted during the current stream, in order of their first message
+static STREAM_CHATTERS: LazyLock<Mutex<IndexSet<Box<str>>>> =
+	LazyLock::new(|| Mutex::new(IndexSet::new()));
+
+static CHAT_HEALTH: LazyLock<RwLock<ChatHealth>> =
+	LazyLock::new(|| RwLock::new(ChatHealth::Disconnected));
+
+// incremented for every new supervisor, so that one which is still shutting down can't overwrite the health of its successor
+static CHAT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// only for privileged users, an action with the same name takes their place
+const BUILTIN_COMMANDS: [&str; 2] = ["redeems", "group"];
+
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// how many health checks in a row may fail before the client gets thrown away and rebuilt
+const MAX_FAILED_CHECKS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum ChatHealth {
+	Disconnected,
+	Connecting,
+	Connected,
+	Degraded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct ChatHealthEvent {
+	pub health: ChatHealth,
+}
+
+pub fn get_chat_health() -> ChatHealth {
+	*CHAT_HEALTH.read()
+}
+
+fn set_chat_health(generation: u64, health: ChatHealth) {
+	if CHAT_GENERATION.load(Ordering::SeqCst) != generation {
+		return;
+	}
+
+	{
+		let mut current = CHAT_HEALTH.write();
+		if *current == health {
+			return;
+		}
+		*current = health;
+	}
+
+	tracing::info!("Chat health: {health:?}");
+	emit_event(ChatHealthEvent { health });
+}
+
+/// Always hands out the newest token, so a refreshed token doesn't need a reconnect
+/// and reconnecting later doesn't use an expired one
+#[derive(Clone)]
+pub struct ChatCredentials {
+	pair: Arc<RwLock<CredentialsPair>>,
+}
 
-	let user_tkn = twitch_client
-		.get_token()
-		.ok_or(ErrorMsg::TokenGone)?
-		.clone();
-	let username = user_tkn.login.to_string();
+impl ChatCredentials {
+	fn new(user_tkn: &UserToken) -> Self {
+		Self {
+			pair: Arc::new(RwLock::new(CredentialsPair {
+				login: user_tkn.login.to_string(),
+				token: Some(user_tkn.access_token.clone().take()), // to string doesn't work because it redacts the token so that you don't print it on accident
+			})),
+		}
+	}
 
-	let client_config = ClientConfig::new_simple(StaticLoginCredentials::new(
-		username.clone(),
-		Some(user_tkn.access_token.clone().take()), // to string doesn't work because it redacts the token so that you don't print it on accident
+	fn set_token(&self, user_tkn: &UserToken) {
+		self.pair.write().token = Some(user_tkn.access_token.clone().take());
+	}
+}
+
+// the derived one would print the token
+impl fmt::Debug for ChatCredentials {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ChatCredentials")
+			.field("login", &self.pair.read().login)
+			.finish_non_exhaustive()
+	}
+}
+
+#[async_trait]
+impl LoginCredentials for ChatCredentials {
+	type Error = Infallible;
+
+	async fn get_credentials(&self) -> Result<CredentialsPair, Self::Error> {
+		Ok(self.pair.read().clone())
+	}
+}
+
+/// Handle to the task keeping the chat connection alive.
+/// Dropping it shuts the connection down cleanly.
+pub struct ChatSupervisor {
+	shutdown: Arc<Notify>,
+	credentials: ChatCredentials,
+}
+
+impl ChatSupervisor {
+	/// The connection stays up, the new token is used for the next login
+	pub fn update_token(&self, user_tkn: &UserToken) {
+		self.credentials.set_token(user_tkn);
+	}
+}
+
+impl Drop for ChatSupervisor {
+	fn drop(&mut self) {
+		// notify_one stores a permit, so the supervisor will see this even if it is currently busy
+		self.shutdown.notify_one();
+	}
+}
+
+enum Disconnect {
+	Shutdown,
+	Closed,
+	Unhealthy,
+}
+
+/// Starts a supervised chat connection for the account the token belongs to
+pub fn chat_listener(user_tkn: &UserToken) -> ChatSupervisor {
+	let channel = user_tkn.login.to_string();
+	let credentials = ChatCredentials::new(user_tkn);
+
+	let shutdown = Arc::new(Notify::new());
+	let generation = CHAT_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+	spawn(supervise(
+		credentials.clone(),
+		channel,
+		generation,
+		shutdown.clone(),
 	));
 
-	let (mut incoming_msg, client) = IrcClient::new(client_config);
+	ChatSupervisor {
+		shutdown,
+		credentials,
+	}
+}
+
+async fn supervise(
+	credentials: ChatCredentials,
+	channel: String,
+	generation: u64,
+	shutdown: Arc<Notify>,
+) {
+	tracing::debug!("Started chat supervisor");
+
+	let mut backoff = MIN_BACKOFF;
+	let mut greeted = false;
+
+	loop {
+		set_chat_health(generation, ChatHealth::Connecting);
+
+		let (mut incoming_msg, client) =
+			IrcClient::new(ClientConfig::new_simple(credentials.clone()));
+		let client = Arc::new(client);
+
+		{
+			let mut tw_client = TWITCH_CLIENT.write().await;
+			if CHAT_GENERATION.load(Ordering::SeqCst) != generation {
+				tracing::debug!("Chat supervisor was replaced, stopping");
+				return;
+			}
+			tw_client.chat_client = Some(client.clone());
+		}
+
+		if let Err(e) = client.join(channel.clone()) {
+			tracing::error!("Couldn't join chat of {channel}: {e}");
+		}
+
+		let mut health_check = interval_at(
+			Instant::now() + HEALTH_CHECK_INTERVAL,
+			HEALTH_CHECK_INTERVAL,
+		);
+		let mut failed_checks = 0;
+
+		let reason = loop {
+			select! {
+				biased;
+				_ = shutdown.notified() => break Disconnect::Shutdown,
+				msg = incoming_msg.recv() => match msg {
+					// the client closed the receiver, nothing will ever arrive here again
+					None => break Disconnect::Closed,
+					Some(msg) => {
+						match &msg {
+							ServerMessage::Join(join) if join.channel_login == channel => {
+								set_chat_health(generation, ChatHealth::Connected);
+								backoff = MIN_BACKOFF;
+								failed_checks = 0;
+
+								if !greeted {
+									greeted = true;
+									let client = client.clone();
+									let channel = channel.clone();
+									spawn(async move {
+										if let Err(e) = client
+											.say(channel, format!("{} initialized! 🧸", NAME_CAPITALIZED))
+											.await
+										{
+											tracing::error!("Couldn't send greeting: {e}");
+										}
+									});
+								}
+							}
+							// twitch-irc reconnects on its own, but until it rejoined we are not really connected
+							ServerMessage::Reconnect(_) => set_chat_health(generation, ChatHealth::Degraded),
+							_ => {}
+						}
 
-	let client = Arc::new(client);
+						spawn(async move {
+							if let Err(e) = handle_msg(msg).await {
+								tracing::error!("Error handling chat msg {e}");
+							};
+						});
+					}
+				},
+				_ = health_check.tick() => {
+					let (_, joined) = client.get_channel_status(channel.clone()).await;
+					if joined {
+						set_chat_health(generation, ChatHealth::Connected);
+						failed_checks = 0;
+						continue;
+					}
 
-	twitch_client.chat_client = Some(client.clone());
+					failed_checks += 1;
+					tracing::warn!("Not joined to chat ({failed_checks}/{MAX_FAILED_CHECKS})");
+					set_chat_health(generation, ChatHealth::Degraded);
 
-	let join_handle = spawn(async move {
-		tracing::debug!("Started chat listener");
-		loop {
-			let msg = incoming_msg.recv().await;
-			match msg {
-				None => tracing::debug!("Received empty msg?"),
-				Some(msg) => {
-					spawn(async move {
-						if let Err(e) = handle_msg(msg).await {
-							tracing::error!("Error handling chat msg {e}");
-						};
-					});
+					if failed_checks >= MAX_FAILED_CHECKS {
+						break Disconnect::Unhealthy;
+					}
+
+					// try to rejoin, in case the join was lost somewhere
+					if let Err(e) = client.join(channel.clone()) {
+						tracing::error!("Couldn't rejoin chat of {channel}: {e}");
+					}
 				}
 			}
+		};
+
+		match reason {
+			Disconnect::Shutdown => {
+				client.part(channel.clone());
+				drop_chat_client(&client).await;
+				set_chat_health(generation, ChatHealth::Disconnected);
+				tracing::debug!("Chat supervisor shut down");
+				return;
+			}
+			Disconnect::Closed => tracing::warn!("Chat connection closed"),
+			Disconnect::Unhealthy => tracing::warn!("Chat connection unhealthy"),
 		}
-	});
 
-	client.join(username.clone())?;
-	client
-		.say(username, format!("{} initialized! 🧸", NAME_CAPITALIZED))
-		.await?;
+		drop_chat_client(&client).await;
+		set_chat_health(generation, ChatHealth::Degraded);
+		tracing::info!("Reconnecting to chat in {}s", backoff.as_secs());
+
+		select! {
+			biased;
+			_ = shutdown.notified() => {
+				set_chat_health(generation, ChatHealth::Disconnected);
+				return;
+			}
+			_ = sleep(backoff) => {}
+		}
+
+		backoff = (backoff * 2).min(MAX_BACKOFF);
+	}
+}
 
-	Ok(join_handle)
+/// Removes the chat client from the global twitch client, unless it was already replaced by a newer one
+async fn drop_chat_client(client: &Arc<IrcClient>) {
+	let mut tw_client = TWITCH_CLIENT.write().await;
+	if tw_client
+		.chat_client
+		.as_ref()
+		.is_some_and(|current| Arc::ptr_eq(current, client))
+	{
+		tw_client.chat_client = None;
+	}
 }
 
 fn register_active_chatter(name: Box<str>) {
 	spawn(async move {
 		let unix = get_unix();
+		STREAM_CHATTERS.lock().await.insert(name.clone());
 		let mut active_chatters = ACTIVE_CHATTERS.lock().await;
 		active_chatters.insert(name, unix);
 	});
 }
 
+/// Everyone who chatted this stream, in the order of their first message
+pub async fn get_stream_chatters() -> Vec<Box<str>> {
+	STREAM_CHATTERS.lock().await.iter().cloned().collect()
+}
+
+pub async fn reset_stream_chatters() {
+	STREAM_CHATTERS.lock().await.clear();
+}
+
 pub async fn is_chatter_active(name: &str) -> bool {
 	let unix = get_unix();
 	let mut active_chatters = ACTIVE_CHATTERS.lock().await;
@@ -91,21 +364,63 @@ pub async fn get_random_chatter() -> Option<Box<str>> {
 	active_chatters.get_index(i).map(|(s, _)| s).cloned()
 }
 
-async fn handle_msg(server_msg: ServerMessage) -> Result<(), Error> {
-	tracing::debug!("Message received: {:?}", server_msg.source().params);
+/// Sends a message to our own channel.
+/// Replies to simulated messages only end up in the transcript.
+pub async fn say(msg: String, simulated: bool) -> Result<(), Error> {
+	if simulated {
+		simulator::record_outgoing(msg);
+		return Ok(());
+	}
 
-	let params = &server_msg.source().params;
+	let (client, channel) = {
+		let tw_client = TWITCH_CLIENT.read().await;
+		match (tw_client.chat_client.clone(), tw_client.get_username()) {
+			(Some(client), Some(channel)) => (client, channel),
+			_ => return Err(Error::new(ErrorMsg::ChatMsgSend)),
+		}
+	};
 
-	let chatter_name = match params.first() {
-		None => return Ok(()),
-		Some(p) => p.as_str(),
+	client.say(channel, msg).await?;
+	Ok(())
+}
+
+/// Moderators and the broadcaster can control the bot from chat
+pub fn is_privileged_badge(badge: &str) -> bool {
+	badge == "broadcaster" || badge == "moderator"
+}
+
+async fn handle_msg(server_msg: ServerMessage) -> Result<(), Error> {
+	let privmsg = match server_msg {
+		ServerMessage::Privmsg(m) => m,
+		_ => return Ok(()),
 	};
-	let (prefix, msg) = match params.get(1) {
+
+	let privileged = privmsg
+		.badges
+		.iter()
+		.any(|badge| is_privileged_badge(badge.name.as_str()));
+
+	handle_chat(
+		privmsg.sender.name.as_str(),
+		privmsg.message_text.as_str(),
+		privileged,
+		false,
+	)
+	.await
+}
+
+/// Handles a chat message, whether it came from twitch or the simulator
+pub async fn handle_chat(
+	chatter_name: &str,
+	text: &str,
+	privileged: bool,
+	simulated: bool,
+) -> Result<(), Error> {
+	tracing::debug!("Message received from {chatter_name}: {text}");
+
+	let (prefix, msg) = match text.split_at_checked(1) {
 		None => return Ok(()),
-		Some(p) => match p.split_at_checked(1) {
-			None => return Ok(()),
-			Some(s) => s,
-		},
+		Some(s) => s,
 	};
 
 	register_active_chatter(Box::from(chatter_name));
@@ -114,8 +429,9 @@ async fn handle_msg(server_msg: ServerMessage) -> Result<(), Error> {
 		_ = TWITCH_CLIENT
 			.read()
 			.await
-			.ban_user(chatter_name, "bot detected", None)
-			.await;
+			.ban_user(chatter_name, "bot detected", None, simulated)
+			.await
+			.inspect_err(|e| tracing::warn!("Couldn't ban bot {chatter_name}: {e}"));
 		return Ok(());
 	}
 
@@ -132,19 +448,75 @@ async fn handle_msg(server_msg: ServerMessage) -> Result<(), Error> {
 		Some(m) => m,
 	};
 
-	let mut action = match get_action(cmd.to_lowercase().as_str()).await {
-		Some(a) => a,
-		None => return Ok(()),
+	let action = match get_action(cmd.to_lowercase().as_str()).await {
+		Some(a) if matches!(a.trigger, Trigger::Command(_)) => Some(a),
+		_ => None,
 	};
 
-	if !action.allow_use() {
-		return Ok(()); // action is still on cooldown
-	}
+	let builtin = BUILTIN_COMMANDS
+		.iter()
+		.find(|builtin| cmd.eq_ignore_ascii_case(builtin));
+
+	// actions the streamer made win over the built in commands
+	let mut action = match (action, builtin) {
+		(Some(a), Some(builtin)) => {
+			if privileged {
+				tracing::info!("The action !{builtin} replaces the built in command");
+			}
+			a
+		}
+		(Some(a), None) => a,
+		// they pause real rewards and disable real actions
+		(None, Some(_)) if privileged && simulated => {
+			simulator::note(format!(
+				"Would run !{msg}, built in commands are skipped while simulating"
+			));
+			return Ok(());
+		}
+		(None, Some(&"redeems")) if privileged => {
+			let args = split.collect::<Vec<_>>().join(" ");
+			let reply = reward_groups::chat_command(args.as_str()).await;
+			say(reply, simulated).await?;
+			return Ok(());
+		}
+		(None, Some(&"group")) if privileged => {
+			let args = split.collect::<Vec<_>>().join(" ");
+			say(action_groups::chat_command(args.as_str()), simulated).await?;
+			return Ok(());
+		}
+		(None, _) => return Ok(()),
+	};
 
 	let msg = split.next();
+	let ctx = ExecCtx {
+		simulated,
+		..ExecCtx::new(chatter_name, msg)
+	};
+
+	// not recorded in the history, a spammy chat would drown out everything else
+	if !action.allow_use(simulated) {
+		tracing::debug!("Skipping {:?}, on cooldown or disabled", action.trigger);
+		return Ok(());
+	}
+
 	tracing::debug!("action: {action:?}; msg: {msg:?}");
 
-	action.exec.exec(chatter_name, msg).await;
+	if let Err(e) = action.run(&ctx).await
+		&& let Some(reply) = error_reply(e.msg)
+	{
+		say(format!("@{chatter_name} {reply}"), simulated).await?;
+	}
 
 	Ok(())
 }
+
+/// What to tell the chatter when their command failed because of them
+fn error_reply(msg: ErrorMsg) -> Option<&'static str> {
+	match msg {
+		ErrorMsg::UserNotFound => Some("user not found"),
+		ErrorMsg::MissingPrompt => Some("this command needs some text after it"),
+		ErrorMsg::MissingTarget => Some("this command has no one to target, it needs fixing"),
+		ErrorMsg::CantBanSelf => Some("nice try"),
+		_ => None,
+	}
+}
@@ -8,6 +8,30 @@ use tokio::task::spawn_blocking;
 struct InnerCounter {
 	counter: u32,
 	template: String,
+	// reset the counter whenever a new stream starts
+	#[serde(default)]
+	reset_per_stream: bool,
+	// the count of simulated actions, starting from the real one, never saved
+	#[serde(skip)]
+	simulated: Option<u32>,
+}
+
+impl InnerCounter {
+	fn count_mut(&mut self, simulated: bool) -> &mut u32 {
+		if !simulated {
+			return &mut self.counter;
+		}
+
+		let real = self.counter;
+		self.simulated.get_or_insert(real)
+	}
+
+	fn count(&self, simulated: bool) -> u32 {
+		match simulated {
+			true => self.simulated.unwrap_or(self.counter),
+			false => self.counter,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -16,34 +40,56 @@ pub struct TwitchCounter {
 }
 
 impl TwitchCounter {
-	pub async fn add(&mut self, to_add: u32) -> String {
+	pub fn resets_per_stream(&self) -> bool {
+		self.inner.read().reset_per_stream
+	}
+
+	/// Simulated actions count on a copy, so the real count stays untouched
+	pub async fn add(&mut self, to_add: u32, simulated: bool) -> String {
 		let inner = self.inner.clone();
 		spawn_blocking(move || {
 			let mut inner = inner.write();
-			inner.counter += to_add;
-			format_counter(&inner)
+			*inner.count_mut(simulated) += to_add;
+			format_counter(&inner, simulated)
 		})
 		.await
 		.unwrap()
 	}
 
-	pub async fn reset(&mut self) -> String {
+	/// What the counter would say after adding to it, or after resetting it with None, without changing it
+	pub fn preview(&self, to_add: Option<u32>, simulated: bool) -> String {
+		let inner = self.inner.read();
+		let counter = match to_add {
+			None => 0,
+			Some(n) => inner.count(simulated) + n,
+		};
+		inner
+			.template
+			.replace("{counter}", counter.to_string().as_str())
+	}
+
+	pub async fn reset(&mut self, simulated: bool) -> String {
 		let inner = self.inner.clone();
 		spawn_blocking(move || {
 			let mut inner = inner.write();
-			inner.counter = 0;
-			format_counter(&inner)
+			*inner.count_mut(simulated) = 0;
+			format_counter(&inner, simulated)
 		})
 		.await
 		.unwrap()
 	}
+
+	/// Forgets the count of the simulation, the real one was never touched
+	pub fn drop_simulated(&self) {
+		self.inner.write().simulated = None;
+	}
 }
 
-fn format_counter(counter: &InnerCounter) -> String {
+fn format_counter(counter: &InnerCounter, simulated: bool) -> String {
 	counter
 		.template
 		.clone()
-		.replace("{counter}", counter.counter.to_string().as_str())
+		.replace("{counter}", counter.count(simulated).to_string().as_str())
 }
 
 impl Serialize for TwitchCounter {
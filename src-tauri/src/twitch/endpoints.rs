@@ -0,0 +1,187 @@
+use std::{
+	io,
+	sync::{Arc, LazyLock},
+};
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use twitch_api::{
+	TWITCH_EVENTSUB_WEBSOCKET_URL, TWITCH_HELIX_URL,
+	client::{BoxedFuture, Client, ClientDefault, Request, Response},
+};
+use twitch_irc::{
+	SecureTCPTransport,
+	transport::{
+		Transport,
+		websocket::{ConnectionUri, WSTransport},
+	},
+};
+use twitch_oauth2::url::Url;
+
+use crate::config::{ARGS, CONFIG};
+
+const OAUTH2_URL: &str = "https://id.twitch.tv/oauth2/";
+const IRC_WS_URL: &str = "wss://irc-ws.chat.twitch.tv";
+
+pub static ENDPOINTS: LazyLock<Endpoints> = LazyLock::new(|| match Endpoints::load() {
+	Ok(e) => e,
+	Err(e) => {
+		tracing::error!("Ignoring the endpoint overrides: {e}");
+		Endpoints::default()
+	}
+});
+
+/// Custom urls for the twitch services, e.g. a local mock server.
+/// Command line arguments win over the config, unset ones talk to twitch.
+#[derive(Debug, Default)]
+pub struct Endpoints {
+	helix: Option<Box<str>>,
+	oauth: Option<Box<str>>,
+	eventsub: Option<Box<str>>,
+	irc: Option<Box<str>>,
+}
+
+impl Endpoints {
+	fn load() -> Result<Self, String> {
+		let config = CONFIG.read().endpoints.clone().unwrap_or_default();
+		let pick = |arg: &Option<Arc<str>>, config: Option<Box<str>>| {
+			arg.as_deref().map(Box::from).or(config)
+		};
+
+		let helix = pick(&ARGS.helix_url, config.helix_url);
+		let oauth = pick(&ARGS.oauth_url, config.oauth_url);
+		let eventsub = pick(&ARGS.eventsub_url, config.eventsub_url);
+		let irc = pick(&ARGS.irc_url, config.irc_url);
+
+		Ok(Self {
+			helix: helix.map(|u| base_url("Helix", &u)).transpose()?,
+			oauth: oauth.map(|u| base_url("OAuth2", &u)).transpose()?,
+			eventsub: eventsub.map(|u| ws_url("EventSub", u)).transpose()?,
+			irc: irc.map(|u| ws_url("IRC", u)).transpose()?,
+		})
+	}
+
+	pub fn eventsub_url(&self) -> &str {
+		match &self.eventsub {
+			None => TWITCH_EVENTSUB_WEBSOCKET_URL.as_str(),
+			Some(url) => url,
+		}
+	}
+
+	/// Points a request meant for twitch at the configured endpoint
+	fn redirect(&self, request: &mut Request) {
+		let uri = request.uri().to_string();
+		let overrides = [
+			(TWITCH_HELIX_URL.as_str(), &self.helix),
+			(OAUTH2_URL, &self.oauth),
+		];
+
+		for (default, custom) in overrides {
+			let (custom, path) = match (custom, uri.strip_prefix(default)) {
+				(Some(custom), Some(path)) => (custom, path),
+				_ => continue,
+			};
+
+			match format!("{custom}{path}").parse() {
+				Ok(redirected) => *request.uri_mut() = redirected,
+				Err(e) => tracing::error!("Couldn't redirect {uri} to {custom}: {e}"),
+			}
+			return;
+		}
+	}
+}
+
+/// Checks the configured endpoints, so broken ones stop the bot right away
+pub fn check_endpoints() -> Result<(), String> {
+	Endpoints::load().map(|_| ())
+}
+
+fn base_url(name: &str, url: &str) -> Result<Box<str>, String> {
+	let mut parsed = Url::parse(url).map_err(|e| format!("Invalid {name} url '{url}': {e}"))?;
+	if !matches!(parsed.scheme(), "http" | "https") {
+		return Err(format!("{name} url '{url}' has to be http or https"));
+	}
+
+	// endpoint paths get appended, without the slash the last segment would be replaced
+	if !parsed.path().ends_with('/') {
+		parsed.set_path(format!("{}/", parsed.path()).as_str());
+	}
+
+	Ok(Box::from(parsed.as_str()))
+}
+
+fn ws_url(name: &str, url: Box<str>) -> Result<Box<str>, String> {
+	let parsed = Url::parse(&url).map_err(|e| format!("Invalid {name} url '{url}': {e}"))?;
+	match parsed.scheme() {
+		"ws" | "wss" => Ok(url),
+		_ => Err(format!("{name} url '{url}' has to be ws or wss")),
+	}
+}
+
+/// Http client for Helix and OAuth2 that sends the requests to the configured endpoints
+#[derive(Clone)]
+pub struct EndpointClient(reqwest::Client);
+
+impl Default for EndpointClient {
+	fn default() -> Self {
+		Self(reqwest::Client::default_client())
+	}
+}
+
+impl Client for EndpointClient {
+	type Error = reqwest::Error;
+
+	fn req(&self, mut request: Request) -> BoxedFuture<'_, Result<Response, Self::Error>> {
+		ENDPOINTS.redirect(&mut request);
+		self.0.req(request)
+	}
+}
+
+/// The configured IRC url, twitch-irc asks for it when connecting
+#[derive(Debug)]
+pub struct IrcUri;
+
+impl ConnectionUri for IrcUri {
+	fn get_server_uri() -> &'static str {
+		ENDPOINTS.irc.as_deref().unwrap_or(IRC_WS_URL)
+	}
+}
+
+/// Connects chat to twitch over TCP, or over a websocket if a custom IRC url is configured.
+/// Uses the same error and stream types as the TCP transport, so the rest of the bot doesn't care which one it got.
+#[derive(Debug)]
+pub enum ChatTransport {
+	Twitch(SecureTCPTransport),
+	Custom(WSTransport<IrcUri>),
+}
+
+#[async_trait]
+impl Transport for ChatTransport {
+	type ConnectError = <SecureTCPTransport as Transport>::ConnectError;
+	type IncomingError = <SecureTCPTransport as Transport>::IncomingError;
+	type OutgoingError = <SecureTCPTransport as Transport>::OutgoingError;
+	type Incoming = <SecureTCPTransport as Transport>::Incoming;
+	type Outgoing = <SecureTCPTransport as Transport>::Outgoing;
+
+	async fn new() -> Result<Self, Self::ConnectError> {
+		match ENDPOINTS.irc {
+			None => Ok(Self::Twitch(SecureTCPTransport::new().await?)),
+			Some(_) => match WSTransport::new().await {
+				Ok(t) => Ok(Self::Custom(t)),
+				Err(e) => Err(io::Error::other(e).into()),
+			},
+		}
+	}
+
+	fn split(self) -> (Self::Incoming, Self::Outgoing) {
+		match self {
+			Self::Twitch(t) => t.split(),
+			Self::Custom(t) => {
+				let (incoming, outgoing) = t.split();
+				let incoming = incoming.map(|msg| msg.map_err(|e| e.map_left(io::Error::other)));
+				let outgoing = outgoing.sink_map_err(io::Error::other);
+				(Box::new(incoming), Box::new(outgoing))
+			}
+		}
+	}
+}
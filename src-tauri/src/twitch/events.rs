@@ -1,93 +1,376 @@
+use std::{sync::Arc, time::Duration};
+
 use futures::StreamExt;
-use tokio::spawn;
-use tokio_tungstenite::connect_async;
-use twitch_api::eventsub::{Event, EventsubWebsocketData, Message};
+use tokio::{
+	net::TcpStream,
+	select, spawn,
+	sync::Notify,
+	time::{sleep, timeout},
+};
+use tokio_tungstenite::{
+	MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message as WsMessage,
+};
+use twitch_api::eventsub::{
+	Event, EventSubscription, EventsubWebsocketData, Message, Payload, Transport,
+};
 
 use crate::{
-	error::Error,
-	twitch::{TWITCH_CLIENT, TwitchClient, redeems::exec_redeem},
+	error::{Error, ErrorMsg},
+	twitch::{
+		TWITCH_CLIENT, TwitchClient,
+		endpoints::ENDPOINTS,
+		notifications, redeems,
+		scopes::Feature,
+		stream::{self, sync_stream_session},
+	},
 };
 
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// used until the welcome message tells us the real one
+const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(10);
+// extra time on top of the keepalive timeout, before the connection is considered dead
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+const WELCOME_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+/// Handle to the task keeping the EventSub session alive.
+/// Dropping it closes the websocket.
+pub struct EventSubSupervisor {
+	shutdown: Arc<Notify>,
+}
+
+impl Drop for EventSubSupervisor {
+	fn drop(&mut self) {
+		self.shutdown.notify_one();
+	}
+}
+
+enum SessionEnd {
+	Lost,
+	Reconnect(String),
+}
+
 impl TwitchClient {
-	pub async fn setup_websocket() -> Result<(), Error> {
-		let (socket, resp) = connect_async("wss://eventsub.wss.twitch.tv/ws").await?;
+	pub fn setup_websocket() -> EventSubSupervisor {
+		let shutdown = Arc::new(Notify::new());
+		spawn(supervise(shutdown.clone()));
+		EventSubSupervisor { shutdown }
+	}
 
-		if let Some(b) = resp.body().as_deref() {
-			let s = str::from_utf8(b);
-			tracing::info!("WebSocket response:\n{s:?}");
+	/// Subscribes to every topic the bot needs on the current websocket session
+	pub async fn subscribe_all(&self) -> Result<(), Error> {
+		match self.sub_new_redeems().await {
+			Err(e) if e.msg == ErrorMsg::MissingScope => {
+				tracing::info!("Skipping redeem subscription, the login is missing scopes for it")
+			}
+			Err(e) => tracing::error!("Error setting up redeem subscription: {e}"),
+			Ok(()) => {}
 		}
 
-		let (_, mut read) = socket.split();
+		if let Err(e) = self.sub_stream_events().await {
+			tracing::error!("Error setting up stream subscriptions: {e}");
+		}
 
-		spawn(async move {
-			while let Some(Ok(msg)) = read.next().await {
-				let msg = match msg.into_text() {
-					Err(_) => continue,
-					Ok(m) => m,
-				};
+		self.sub_channel_events().await?;
+		Ok(())
+	}
 
-				let event = match Event::parse_websocket(msg.as_str()) {
-					Err(_) => continue,
-					Ok(e) => e,
-				};
+	/// Creates a subscription on the current websocket session
+	pub async fn subscribe<E: EventSubscription + Send>(&self, event: E) -> Result<(), Error> {
+		let user_token = match &self.token {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
 
-				{
-					let mut tw_client = TWITCH_CLIENT.write().await;
-					tw_client.websocket_last_event = Some(std::time::SystemTime::now());
-					// auto-drop TWITCH_CLIENT lock
-				}
+		let ws_id = match &self.websocket_id {
+			None => return Err(Error::new(ErrorMsg::WebSocketSetup)),
+			Some(w) => w,
+		};
+
+		self.client
+			.create_eventsub_subscription(event, Transport::websocket(ws_id), user_token.as_ref())
+			.await?;
+
+		tracing::debug!("Setup {} subscription", E::EVENT_TYPE);
+
+		Ok(())
+	}
+
+	/// Only subscribes if the login has the scopes the feature needs
+	pub async fn subscribe_for<E: EventSubscription + Send>(
+		&self,
+		feature: Feature,
+		event: E,
+	) -> Result<(), Error> {
+		if !self.has_feature(feature) {
+			return Err(Error::new(ErrorMsg::MissingScope));
+		}
+
+		self.subscribe(event).await
+	}
+}
+
+async fn supervise(shutdown: Arc<Notify>) {
+	let mut backoff = MIN_BACKOFF;
+	// a session we were moved to by a reconnect message, it keeps its subscriptions
+	let mut reconnected: Option<(WsStream, Duration)> = None;
+
+	loop {
+		let (mut socket, keepalive) = match reconnected.take() {
+			Some(session) => session,
+			None => {
+				let res = select! {
+					biased;
+					_ = shutdown.notified() => return,
+					res = fresh_session() => res,
+				};
 
-				match event {
-					EventsubWebsocketData::Welcome {
-						metadata: _,
-						payload,
-					} => {
-						let mut twitch_client = TWITCH_CLIENT.write().await;
-						twitch_client.websocket_id = Some(payload.session.id.into());
-
-						if let Err(e) = twitch_client.sub_new_redeems().await {
-							tracing::error!("Error setting up redeem subscription: {e}");
-						};
+				match res {
+					Ok(session) => {
+						backoff = MIN_BACKOFF;
+						session
 					}
-					EventsubWebsocketData::Notification {
-						metadata: _,
-						payload,
-					} => match payload {
-						Event::ChannelPointsCustomRewardRedemptionAddV1(payload) => {
-							match payload.message {
-								Message::Notification(event) => {
-									tracing::info!(
-										"{} redeemed: {} ({}); {}",
-										event.user_name,
-										event.reward.title,
-										event.reward.id,
-										event.reward.prompt
-									);
-
-									// TODO: auto-remove event from rewards queue as successfull or reject them
-									_ = exec_redeem(
-										event.user_name.as_str(),
-										event.reward.id.as_str(),
-										event.reward.prompt.as_str(),
-									);
-								}
-								p => tracing::warn!("Unknown event msg:\n{p:#?}"),
-							}
+					Err(e) => {
+						tracing::error!(
+							"Error setting up EventSub session, retrying in {}s: {e}",
+							backoff.as_secs()
+						);
+
+						select! {
+							biased;
+							_ = shutdown.notified() => return,
+							_ = sleep(backoff) => {}
 						}
-						_ => tracing::warn!("Unknown notification:\n{payload:#?}"),
-					},
-					EventsubWebsocketData::Keepalive {
-						metadata: _,
-						payload: _,
-					} => {
-						// do nothing
-						// we track the heartbeat timer further up
+
+						backoff = (backoff * 2).min(MAX_BACKOFF);
+						continue;
 					}
-					_ => tracing::warn!("Unhandled websocket event:\n{:#?}", event),
 				}
 			}
-		});
+		};
 
-		Ok(())
+		let end = select! {
+			biased;
+			_ = shutdown.notified() => {
+				_ = socket.close(None).await;
+				tracing::debug!("EventSub supervisor shut down");
+				return;
+			}
+			end = run_session(&mut socket, keepalive) => end,
+		};
+
+		match end {
+			SessionEnd::Lost => {
+				tracing::warn!("EventSub session lost, starting a new one");
+				_ = socket.close(None).await;
+			}
+			SessionEnd::Reconnect(url) => {
+				tracing::info!("EventSub asked us to reconnect");
+				match reconnect(&mut socket, keepalive, &url).await {
+					Ok(session) => reconnected = Some(session),
+					Err(e) => tracing::warn!("Couldn't follow EventSub reconnect: {e}"),
+				}
+				_ = socket.close(None).await;
+			}
+		}
+	}
+}
+
+/// Connects to EventSub and subscribes to all topics.
+/// Returns the socket and keepalive timeout of the new session.
+async fn fresh_session() -> Result<(WsStream, Duration), Error> {
+	let (mut socket, resp) = connect_async(ENDPOINTS.eventsub_url()).await?;
+
+	if let Some(b) = resp.body().as_deref() {
+		let s = str::from_utf8(b);
+		tracing::info!("WebSocket response:\n{s:?}");
+	}
+
+	let keepalive = wait_for_welcome(&mut socket).await?;
+
+	TWITCH_CLIENT.read().await.subscribe_all().await?;
+
+	// we might have missed the stream going on- or offline while not connected
+	sync_stream_session().await;
+
+	Ok((socket, keepalive))
+}
+
+/// Connects to the reconnect url, while still handling notifications of the old session until the new one is welcomed.
+/// Subscriptions move along with the session, so there is no need to subscribe again.
+async fn reconnect(
+	old: &mut WsStream,
+	keepalive: Duration,
+	url: &str,
+) -> Result<(WsStream, Duration), Error> {
+	let (mut socket, _) = connect_async(url).await?;
+
+	let mut old_open = true;
+	let keepalive = {
+		let welcome = wait_for_welcome(&mut socket);
+		tokio::pin!(welcome);
+
+		loop {
+			select! {
+				res = &mut welcome => break res?,
+				text = timeout(keepalive + KEEPALIVE_GRACE, next_text(old)), if old_open => match text {
+					Ok(Ok(text)) => {
+						handle_text(&text);
+					}
+					_ => old_open = false,
+				},
+			}
+		}
+	};
+
+	Ok((socket, keepalive))
+}
+
+async fn wait_for_welcome(socket: &mut WsStream) -> Result<Duration, Error> {
+	let text = match timeout(WELCOME_TIMEOUT, next_text(socket)).await {
+		Err(_) => return Err(Error::from("No EventSub welcome received")),
+		Ok(text) => text?,
+	};
+
+	let payload = match Event::parse_websocket(text.as_str()) {
+		Ok(EventsubWebsocketData::Welcome {
+			metadata: _,
+			payload,
+		}) => payload,
+		Ok(other) => {
+			return Err(Error::from(format!(
+				"Expected EventSub welcome, got:\n{other:#?}"
+			)));
+		}
+		Err(e) => return Err(Error::from(format!("Invalid EventSub welcome: {e}"))),
+	};
+
+	let keepalive = payload
+		.session
+		.keepalive_timeout_seconds
+		.and_then(|secs| u64::try_from(secs).ok())
+		.map(Duration::from_secs)
+		.unwrap_or(DEFAULT_KEEPALIVE);
+
+	tracing::debug!(
+		"EventSub session {} welcomed, keepalive {}s",
+		payload.session.id,
+		keepalive.as_secs()
+	);
+
+	TWITCH_CLIENT.write().await.websocket_id = Some(payload.session.id.as_ref().into());
+
+	Ok(keepalive)
+}
+
+/// Handles messages until the session ends.
+/// Twitch sends at least a keepalive within the keepalive timeout, if not the session is considered dead.
+async fn run_session(socket: &mut WsStream, keepalive: Duration) -> SessionEnd {
+	loop {
+		let text = match timeout(keepalive + KEEPALIVE_GRACE, next_text(socket)).await {
+			Err(_) => {
+				tracing::warn!(
+					"No EventSub message within {}s",
+					(keepalive + KEEPALIVE_GRACE).as_secs()
+				);
+				return SessionEnd::Lost;
+			}
+			Ok(Err(e)) => {
+				tracing::warn!("EventSub websocket error: {e}");
+				return SessionEnd::Lost;
+			}
+			Ok(Ok(text)) => text,
+		};
+
+		if let Some(end) = handle_text(&text) {
+			return end;
+		}
+	}
+}
+
+/// Returns the next text message, skipping everything else
+async fn next_text(socket: &mut WsStream) -> Result<String, Error> {
+	loop {
+		let msg = match socket.next().await {
+			None => return Err(Error::from("EventSub websocket closed")),
+			Some(msg) => msg?,
+		};
+
+		match msg {
+			WsMessage::Text(text) => return Ok(text.as_str().to_owned()),
+			WsMessage::Close(frame) => {
+				return Err(Error::from(format!("EventSub websocket closed: {frame:?}")));
+			}
+			_ => continue, // pings get answered by tungstenite
+		}
+	}
+}
+
+fn handle_text(text: &str) -> Option<SessionEnd> {
+	let event = match Event::parse_websocket(text) {
+		Err(e) => {
+			tracing::warn!("Couldn't parse EventSub message: {e}");
+			return None;
+		}
+		Ok(e) => e,
+	};
+
+	match event {
+		EventsubWebsocketData::Reconnect {
+			metadata: _,
+			payload,
+		} => match payload.session.reconnect_url {
+			Some(url) => return Some(SessionEnd::Reconnect(url.to_string())),
+			None => return Some(SessionEnd::Lost),
+		},
+		EventsubWebsocketData::Notification {
+			metadata: _,
+			payload,
+		} => handle_notification(payload),
+		EventsubWebsocketData::Keepalive {
+			metadata: _,
+			payload: _,
+		} => {
+			// do nothing
+			// the watchdog only cares that something arrived
+		}
+		EventsubWebsocketData::Revocation {
+			metadata,
+			payload: _,
+		} => tracing::warn!("EventSub subscription revoked: {metadata:#?}"),
+		_ => tracing::warn!("Unhandled websocket event:\n{:#?}", event),
+	}
+
+	None
+}
+
+fn handle_notification(payload: Event) {
+	match payload {
+		Event::ChannelPointsCustomRewardRedemptionAddV1(payload) => {
+			on_message(payload, redeems::on_redemption)
+		}
+		Event::ChannelFollowV2(payload) => on_message(payload, notifications::on_follow),
+		Event::ChannelSubscribeV1(payload) => on_message(payload, notifications::on_subscribe),
+		Event::ChannelSubscriptionMessageV1(payload) => {
+			on_message(payload, notifications::on_resub)
+		}
+		Event::ChannelSubscriptionGiftV1(payload) => {
+			on_message(payload, notifications::on_gift_sub)
+		}
+		Event::ChannelCheerV1(payload) => on_message(payload, notifications::on_cheer),
+		Event::ChannelRaidV1(payload) => on_message(payload, notifications::on_raid),
+		Event::StreamOnlineV1(payload) => on_message(payload, stream::on_online),
+		Event::StreamOfflineV1(payload) => on_message(payload, stream::on_offline),
+		Event::ChannelUpdateV2(payload) => on_message(payload, stream::on_channel_update),
+		_ => tracing::warn!("Unknown notification:\n{payload:#?}"),
+	}
+}
+
+fn on_message<E: EventSubscription>(payload: Payload<E>, handler: fn(E::Payload)) {
+	match payload.message {
+		Message::Notification(event) => handler(event),
+		_ => tracing::warn!("Unexpected {} message", E::EVENT_TYPE),
 	}
 }
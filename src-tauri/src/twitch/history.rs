@@ -0,0 +1,177 @@
+use std::{
+	collections::VecDeque,
+	fs::{self, File, OpenOptions},
+	io::{BufRead, BufReader, ErrorKind, Write},
+	path::PathBuf,
+	sync::{LazyLock, nonpoison::Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri_specta::Event;
+
+use crate::{
+	error::Error,
+	utils::{CFG_DIR_PATH, emit_event, get_unix_milli},
+};
+
+static HISTORY_PATH: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("history.jsonl"));
+// the history before the last rotation, anything older is gone
+static OLD_HISTORY_PATH: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("history.1.jsonl"));
+
+// rotate once the file is this big, so it can't grow forever
+const MAX_HISTORY_SIZE: u64 = 4 * 1024 * 1024;
+
+// keeps appends from different tasks from interleaving
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum ExecStatus {
+	Success,
+	Failed,
+	/// Didn't run, e.g. because of the cooldown
+	Skipped,
+}
+
+/// One run of an action
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct HistoryEntry {
+	/// Unix time in milliseconds
+	pub at: u64,
+	pub trigger: Box<str>,
+	pub user: Box<str>,
+	pub prompt: Option<Box<str>>,
+	pub status: ExecStatus,
+	pub error: Option<Box<str>>,
+	/// What the exec tree did, step by step
+	#[serde(default)]
+	pub trace: Vec<String>,
+	/// Triggered by the simulator, nothing happened on twitch
+	#[serde(default)]
+	pub simulated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct HistoryEvent {
+	pub entry: HistoryEntry,
+}
+
+/// Fields that are None match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct HistoryFilter {
+	pub trigger: Option<String>,
+	/// Case insensitive
+	pub user: Option<String>,
+	pub status: Option<ExecStatus>,
+	/// Unix time in milliseconds
+	pub since: Option<u64>,
+	/// Unix time in milliseconds
+	pub until: Option<u64>,
+	/// Only the newest entries, defaults to 200
+	pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+	fn matches(&self, entry: &HistoryEntry) -> bool {
+		self.trigger
+			.as_deref()
+			.is_none_or(|t| entry.trigger.as_ref() == t)
+			&& self
+				.user
+				.as_deref()
+				.is_none_or(|u| entry.user.eq_ignore_ascii_case(u))
+			&& self.status.is_none_or(|s| entry.status == s)
+			&& self.since.is_none_or(|since| entry.at >= since)
+			&& self.until.is_none_or(|until| entry.at <= until)
+	}
+}
+
+impl HistoryEntry {
+	pub fn new(
+		trigger: &str,
+		user: &str,
+		prompt: Option<&str>,
+		status: ExecStatus,
+		error: Option<String>,
+		trace: Vec<String>,
+		simulated: bool,
+	) -> Self {
+		Self {
+			at: get_unix_milli(),
+			trigger: Box::from(trigger),
+			user: Box::from(user),
+			prompt: prompt.map(Box::from),
+			status,
+			error: error.map(String::into_boxed_str),
+			trace,
+			simulated,
+		}
+	}
+}
+
+fn append(entry: &HistoryEntry) -> Result<(), Error> {
+	let mut line = serde_json::to_string(entry)?;
+	line.push('\n');
+
+	let _guard = WRITE_LOCK.lock();
+	fs::create_dir_all(CFG_DIR_PATH.as_path())?;
+
+	if fs::metadata(HISTORY_PATH.as_path()).is_ok_and(|m| m.len() >= MAX_HISTORY_SIZE) {
+		fs::rename(HISTORY_PATH.as_path(), OLD_HISTORY_PATH.as_path())?;
+	}
+
+	let mut f = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(HISTORY_PATH.as_path())?;
+	f.write_all(line.as_bytes())?;
+
+	Ok(())
+}
+
+/// Stores the entry and tells the frontend about it
+pub fn record(entry: HistoryEntry) {
+	if let Err(e) = append(&entry) {
+		tracing::error!("Couldn't write action history: {e}");
+	}
+
+	emit_event(HistoryEvent { entry });
+}
+
+/// Newest entries first
+pub fn query(filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, Error> {
+	let limit = filter.limit.unwrap_or(200);
+	if limit == 0 {
+		return Ok(Vec::new());
+	}
+
+	// read line by line and only keep the newest matches, the files can be large
+	let mut newest = VecDeque::with_capacity(limit.min(1024));
+	for path in [OLD_HISTORY_PATH.as_path(), HISTORY_PATH.as_path()] {
+		let f = match File::open(path) {
+			Err(e) if e.kind() == ErrorKind::NotFound => continue,
+			res => res?,
+		};
+
+		for line in BufReader::new(f).lines() {
+			let entry = match serde_json::from_str::<HistoryEntry>(line?.as_str()) {
+				Err(e) => {
+					tracing::warn!("Skipping broken history line: {e}");
+					continue;
+				}
+				Ok(entry) => entry,
+			};
+
+			if !filter.matches(&entry) {
+				continue;
+			}
+
+			if newest.len() == limit {
+				newest.pop_front();
+			}
+			newest.push_back(entry);
+		}
+	}
+
+	Ok(newest.into_iter().rev().collect())
+}
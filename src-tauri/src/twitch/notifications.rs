@@ -0,0 +1,213 @@
+use tokio::spawn;
+use twitch_api::{
+	eventsub::channel::{
+		ChannelCheerV1, ChannelCheerV1Payload, ChannelFollowV2, ChannelFollowV2Payload,
+		ChannelRaidV1, ChannelRaidV1Payload, ChannelSubscribeV1, ChannelSubscribeV1Payload,
+		ChannelSubscriptionGiftV1, ChannelSubscriptionGiftV1Payload, ChannelSubscriptionMessageV1,
+		ChannelSubscriptionMessageV1Payload,
+	},
+	types::SubscriptionTier,
+};
+
+use crate::{
+	error::{Error, ErrorMsg},
+	twitch::{
+		TwitchClient,
+		actions::{ExecCtx, Trigger, get_action},
+		scopes::Feature,
+	},
+};
+
+const ANONYMOUS: &str = "Anonymous";
+
+impl TwitchClient {
+	/// Subscribes to follows, subs, resubs, gift subs, cheers and raids.
+	/// A failing topic doesn't stop the others, e.g. follows need moderator rights.
+	pub async fn sub_channel_events(&self) -> Result<(), Error> {
+		let id = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i.id.clone(),
+		};
+
+		let results = [
+			(
+				"follow",
+				self.subscribe_for(
+					Feature::Follows,
+					ChannelFollowV2::new(id.clone(), id.clone()),
+				)
+				.await,
+			),
+			(
+				"subscribe",
+				self.subscribe_for(
+					Feature::Subscriptions,
+					ChannelSubscribeV1::broadcaster_user_id(id.clone()),
+				)
+				.await,
+			),
+			(
+				"resub",
+				self.subscribe_for(
+					Feature::Subscriptions,
+					ChannelSubscriptionMessageV1::broadcaster_user_id(id.clone()),
+				)
+				.await,
+			),
+			(
+				"gift sub",
+				self.subscribe_for(
+					Feature::Subscriptions,
+					ChannelSubscriptionGiftV1::broadcaster_user_id(id.clone()),
+				)
+				.await,
+			),
+			(
+				"cheer",
+				self.subscribe_for(
+					Feature::Bits,
+					ChannelCheerV1::broadcaster_user_id(id.clone()),
+				)
+				.await,
+			),
+			(
+				"raid",
+				self.subscribe(ChannelRaidV1::to_broadcaster_user_id(id))
+					.await,
+			),
+		];
+
+		for (topic, res) in results {
+			match res {
+				Err(e) if e.msg == ErrorMsg::MissingScope => tracing::info!(
+					"Skipping {topic} subscription, the login is missing scopes for it"
+				),
+				Err(e) => tracing::error!("Error setting up {topic} subscription: {e}"),
+				Ok(()) => {}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn tier_name(tier: &SubscriptionTier) -> &str {
+	match tier {
+		SubscriptionTier::Tier1 => "1",
+		SubscriptionTier::Tier2 => "2",
+		SubscriptionTier::Tier3 => "3",
+		SubscriptionTier::Prime => "Prime",
+		SubscriptionTier::Other(s) => s.as_str(),
+	}
+}
+
+/// Runs the action linked to an event trigger.
+/// Events skip the action cooldown, otherwise a sub train would only get thanked once.
+pub fn fire(trigger: Trigger, ctx: ExecCtx) {
+	spawn(async move {
+		let mut action = match get_action(&trigger).await {
+			None => return,
+			Some(a) => a,
+		};
+
+		if action.disabled {
+			action.skip(&ctx, "Disabled");
+			return;
+		}
+
+		tracing::debug!("action: {action:?}; ctx: {ctx:?}");
+		// failures are already logged and recorded in the history
+		_ = action.run(&ctx).await;
+	});
+}
+
+pub fn on_follow(event: ChannelFollowV2Payload) {
+	tracing::info!("{} followed", event.user_name);
+	fire(
+		Trigger::Follow,
+		ExecCtx::new(event.user_name.as_str(), None),
+	);
+}
+
+pub fn on_subscribe(event: ChannelSubscribeV1Payload) {
+	// every gifted sub also shows up here, those get handled by on_gift_sub instead
+	if event.is_gift {
+		return;
+	}
+
+	tracing::info!("{} subscribed", event.user_name);
+	fire(
+		Trigger::Subscribe,
+		ExecCtx::new(event.user_name.as_str(), None).with_var("tier", tier_name(&event.tier)),
+	);
+}
+
+pub fn on_resub(event: ChannelSubscriptionMessageV1Payload) {
+	tracing::info!(
+		"{} resubscribed for {} months: {}",
+		event.user_name,
+		event.cumulative_months,
+		event.message.text
+	);
+
+	let message = match event.message.text.is_empty() {
+		true => None,
+		false => Some(event.message.text.as_str()),
+	};
+
+	fire(
+		Trigger::Resub,
+		ExecCtx::new(event.user_name.as_str(), message)
+			.with_var("tier", tier_name(&event.tier))
+			.with_var("months", event.cumulative_months)
+			.with_var("streak", event.streak_months.unwrap_or_default()),
+	);
+}
+
+pub fn on_gift_sub(event: ChannelSubscriptionGiftV1Payload) {
+	let user = match &event.user_name {
+		Some(name) if !event.is_anonymous => name.as_str(),
+		_ => ANONYMOUS,
+	};
+
+	tracing::info!("{user} gifted {} subs", event.total);
+	fire(
+		Trigger::GiftSub,
+		ExecCtx::new(user, None)
+			.with_var("tier", tier_name(&event.tier))
+			.with_var("amount", event.total)
+			.with_var("total", event.cumulative_total.unwrap_or(event.total)),
+	);
+}
+
+pub fn on_cheer(event: ChannelCheerV1Payload) {
+	let user = match &event.user_name {
+		Some(name) if !event.is_anonymous => name.as_str(),
+		_ => ANONYMOUS,
+	};
+
+	tracing::info!("{user} cheered {} bits: {}", event.bits, event.message);
+
+	let message = match event.message.is_empty() {
+		true => None,
+		false => Some(event.message.as_str()),
+	};
+
+	fire(
+		Trigger::Cheer,
+		ExecCtx::new(user, message).with_var("bits", event.bits),
+	);
+}
+
+pub fn on_raid(event: ChannelRaidV1Payload) {
+	tracing::info!(
+		"{} raided with {} viewers",
+		event.from_broadcaster_user_name,
+		event.viewers
+	);
+	fire(
+		Trigger::Raid,
+		ExecCtx::new(event.from_broadcaster_user_name.as_str(), None)
+			.with_var("viewers", event.viewers),
+	);
+}
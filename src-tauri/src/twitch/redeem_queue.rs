@@ -0,0 +1,261 @@
+use std::{
+	fs,
+	path::PathBuf,
+	sync::{LazyLock, nonpoison::Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri_specta::Event;
+use twitch_api::{
+	eventsub::channel::{
+		ChannelPointsCustomRewardRedemptionAddV1Payload,
+		channel_points_custom_reward_redemption::RedemptionStatus,
+	},
+	helix::points::CustomRewardRedemptionStatus,
+};
+
+use crate::{
+	error::{Error, ErrorMsg},
+	twitch::{
+		actions::{ExecCtx, get_redeem_action},
+		redeems::set_redemption_status,
+	},
+	utils::{CFG_DIR_PATH, emit_event, get_unix},
+};
+
+static QUEUE_PATH: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("redeem_queue.json"));
+
+static REDEEM_QUEUE: LazyLock<Mutex<Vec<PendingRedemption>>> =
+	LazyLock::new(|| Mutex::new(load_queue()));
+
+/// A redemption waiting for the streamer to approve or reject it
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct PendingRedemption {
+	pub id: Box<str>,
+	pub reward_id: Box<str>,
+	pub reward_title: Box<str>,
+	pub user: Box<str>,
+	pub prompt: Box<str>,
+	pub redeemed_at: u64,
+	/// Injected by the simulator, there is nothing to fulfill or refund on twitch
+	#[serde(default)]
+	pub simulated: bool,
+	/// The reward skips the request queue, so twitch fulfilled it already and it can't be refunded.
+	/// A review policy still holds back the action until it is approved.
+	#[serde(default)]
+	pub skipped_queue: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct RedeemQueueEvent {
+	pub queue: Vec<PendingRedemption>,
+}
+
+impl From<&ChannelPointsCustomRewardRedemptionAddV1Payload> for PendingRedemption {
+	fn from(event: &ChannelPointsCustomRewardRedemptionAddV1Payload) -> Self {
+		let redeemed_at = chrono::DateTime::parse_from_rfc3339(event.redeemed_at.as_str())
+			.ok()
+			.and_then(|t| u64::try_from(t.timestamp()).ok())
+			.unwrap_or_else(get_unix);
+
+		Self {
+			id: event.id.as_str().into(),
+			reward_id: event.reward.id.as_str().into(),
+			reward_title: event.reward.title.as_str().into(),
+			user: event.user_name.as_str().into(),
+			prompt: event.user_input.as_str().into(),
+			redeemed_at,
+			simulated: false,
+			skipped_queue: event.status != RedemptionStatus::Unfulfilled,
+		}
+	}
+}
+
+fn load_queue() -> Vec<PendingRedemption> {
+	let s = match fs::read_to_string(QUEUE_PATH.as_path()) {
+		Err(_) => return Vec::new(), // nothing queued yet
+		Ok(s) => s,
+	};
+
+	match serde_json::from_str(s.as_str()) {
+		Err(e) => {
+			tracing::warn!("Couldn't read the redeem queue: {e}");
+			Vec::new()
+		}
+		Ok(q) => q,
+	}
+}
+
+fn save_queue(queue: &[PendingRedemption]) -> Result<(), Error> {
+	// simulated redemptions only live until the simulation ends
+	let real: Vec<&PendingRedemption> = queue.iter().filter(|pending| !pending.simulated).collect();
+
+	fs::create_dir_all(CFG_DIR_PATH.as_path())?;
+	let s = serde_json::to_string_pretty(&real)?;
+	fs::write(QUEUE_PATH.as_path(), s)?;
+	Ok(())
+}
+
+/// Saves the queue and tells the frontend about it.
+/// Call it with the lock still held, otherwise an older snapshot could overwrite a newer one.
+fn queue_changed(queue: &[PendingRedemption]) {
+	if let Err(e) = save_queue(queue) {
+		tracing::error!("Couldn't save the redeem queue: {e}");
+	}
+
+	emit_event(RedeemQueueEvent {
+		queue: queue.to_vec(),
+	});
+}
+
+pub fn get_redeem_queue() -> Vec<PendingRedemption> {
+	REDEEM_QUEUE.lock().clone()
+}
+
+pub fn enqueue(item: PendingRedemption) {
+	tracing::info!("Queued {} from {} for review", item.reward_title, item.user);
+
+	let mut queue = REDEEM_QUEUE.lock();
+	// eventsub may deliver the same notification twice
+	if queue.iter().any(|pending| pending.id == item.id) {
+		return;
+	}
+	queue.push(item);
+
+	queue_changed(&queue);
+}
+
+/// Drops the simulated redemptions, called when the simulation ends
+pub fn drop_simulated() {
+	let mut queue = REDEEM_QUEUE.lock();
+	let len = queue.len();
+	queue.retain(|pending| !pending.simulated);
+
+	if queue.len() != len {
+		queue_changed(&queue);
+	}
+}
+
+fn take(id: &str) -> Option<PendingRedemption> {
+	let mut queue = REDEEM_QUEUE.lock();
+	let pos = queue.iter().position(|pending| pending.id.as_ref() == id)?;
+	let item = queue.remove(pos);
+
+	queue_changed(&queue);
+	Some(item)
+}
+
+/// Puts an item back where it was, e.g. after approving it failed
+fn restore(item: PendingRedemption) {
+	let mut queue = REDEEM_QUEUE.lock();
+	let pos = queue
+		.iter()
+		.position(|pending| pending.redeemed_at > item.redeemed_at)
+		.unwrap_or(queue.len());
+	queue.insert(pos, item);
+
+	queue_changed(&queue);
+}
+
+/// Runs the linked action and fulfills the redemption.
+/// The streamer already decided, so the action cooldown doesn't apply.
+pub async fn approve_redemption(id: &str) -> Result<(), Error> {
+	let item = match take(id) {
+		None => return Err(Error::from(format!("No queued redemption {id}"))),
+		Some(i) => i,
+	};
+
+	// fulfilling it without running anything would take the points for nothing
+	let mut action =
+		match get_redeem_action(item.reward_id.as_ref(), item.reward_title.as_ref()).await {
+			None => {
+				let e = format!(
+					"{} has no action, reject it to refund it",
+					item.reward_title
+				);
+				restore(item);
+				return Err(Error::from(e));
+			}
+			Some(a) if a.disabled => {
+				let e = format!("The action of {} is disabled", item.reward_title);
+				restore(item);
+				return Err(Error::from(e));
+			}
+			Some(a) => a,
+		};
+
+	let prompt = match item.prompt.is_empty() {
+		true => None,
+		false => Some(item.prompt.as_ref()),
+	};
+
+	let ctx = ExecCtx {
+		simulated: item.simulated,
+		..ExecCtx::new(item.user.as_ref(), prompt)
+	};
+
+	if let Err(e) = action.run(&ctx).await {
+		restore(item);
+		return Err(e);
+	}
+
+	let res = set_redemption_status(&item, CustomRewardRedemptionStatus::Fulfilled).await;
+
+	match res {
+		// the action ran, the streamer has to fulfill it on twitch
+		Err(e) if e.msg == ErrorMsg::RewardNotManageable => Ok(()),
+		res => res,
+	}
+}
+
+/// Refunds the redemption without running its action
+pub async fn reject_redemption(id: &str) -> Result<(), Error> {
+	let item = match take(id) {
+		None => return Err(Error::from(format!("No queued redemption {id}"))),
+		Some(i) => i,
+	};
+
+	tracing::info!("Rejected {} from {}", item.reward_title, item.user);
+
+	let res = set_redemption_status(&item, CustomRewardRedemptionStatus::Canceled).await;
+
+	match res {
+		// the streamer has to refund it on twitch, keeping it here wouldn't help
+		Err(e) if e.msg == ErrorMsg::RewardNotManageable => Err(e),
+		Err(e) => {
+			// without the refund the points would be gone, so keep it around to try again
+			restore(item);
+			Err(e)
+		}
+		Ok(()) => Ok(()),
+	}
+}
+
+/// Approves every given redemption in order.
+/// Returns the ids that failed.
+pub async fn approve_redemptions(ids: &[String]) -> Vec<String> {
+	let mut failed = Vec::new();
+	for id in ids {
+		if let Err(e) = approve_redemption(id).await {
+			tracing::error!("Couldn't approve redemption {id}: {e}");
+			failed.push(id.clone());
+		}
+	}
+
+	failed
+}
+
+/// Rejects every given redemption.
+/// Returns the ids that failed.
+pub async fn reject_redemptions(ids: &[String]) -> Vec<String> {
+	let mut failed = Vec::new();
+	for id in ids {
+		if let Err(e) = reject_redemption(id).await {
+			tracing::error!("Couldn't reject redemption {id}: {e}");
+			failed.push(id.clone());
+		}
+	}
+
+	failed
+}
@@ -1,17 +1,135 @@
+use std::{ops::Deref, sync::LazyLock};
+
+use dashmap::DashSet;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::spawn;
 use twitch_api::{
-	eventsub::{Transport, channel::ChannelPointsCustomRewardRedemptionAddV1},
-	helix::points::CustomReward,
+	eventsub::channel::{
+		ChannelPointsCustomRewardRedemptionAddV1, ChannelPointsCustomRewardRedemptionAddV1Payload,
+	},
+	helix::points::{
+		CreateCustomRewardBody, CreateCustomRewardRequest, CustomReward,
+		CustomRewardRedemptionStatus, DeleteCustomRewardRequest, UpdateCustomRewardBody,
+		UpdateCustomRewardRequest, UpdateRedemptionStatusBody, UpdateRedemptionStatusRequest,
+	},
+	types::Max,
 };
 
 use crate::{
 	config::CONFIG,
 	error::{Error, ErrorMsg},
 	twitch::{
-		TwitchClient,
-		actions::{Trigger, get_action},
+		TWITCH_CLIENT, TwitchClient,
+		actions::{
+			Action, ArcStr, ExecCtx, RedeemPolicy, Trigger, get_all_actions, get_redeem_action,
+			relink_action, set_reward_title,
+		},
+		redeem_queue::{PendingRedemption, enqueue},
+		scopes::Feature,
+		simulator,
 	},
+	utils::NAME_CAPITALIZED,
 };
 
+// rewards created with our client id, only those can be edited, fulfilled or refunded by the bot
+static MANAGEABLE_REWARDS: LazyLock<DashSet<Box<str>>> = LazyLock::new(DashSet::new);
+
+pub struct RedeemOutcome {
+	pub success: bool,
+	pub policy: RedeemPolicy,
+}
+
+/// Every field of a channel point reward
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+pub struct FrontendReward {
+	pub id: String,
+	pub title: String,
+	pub prompt: String,
+	pub cost: usize,
+	pub image: Option<String>,
+	pub default_image: Option<String>,
+	pub background_color: String,
+	pub is_enabled: bool,
+	pub is_user_input_required: bool,
+	/// None if there is no limit
+	pub max_per_stream: Option<u64>,
+	/// None if there is no limit
+	pub max_per_user_per_stream: Option<u64>,
+	/// None if there is no cooldown
+	pub global_cooldown_seconds: Option<u64>,
+	pub is_paused: bool,
+	pub is_in_stock: bool,
+	pub should_redemptions_skip_request_queue: bool,
+	pub redemptions_redeemed_current_stream: Option<usize>,
+	pub cooldown_expires_at: Option<String>,
+	/// Created by the bot, so it can be edited and its redemptions fulfilled or refunded
+	pub manageable: bool,
+}
+
+fn max_setting(max: &Max) -> Option<u64> {
+	match max {
+		Max::MaxPerStream {
+			is_enabled,
+			max_per_stream,
+		} => is_enabled.then_some(*max_per_stream as u64),
+		Max::MaxPerUserPerStream {
+			is_enabled,
+			max_per_user_per_stream,
+		} => is_enabled.then_some(*max_per_user_per_stream as u64),
+		_ => None,
+	}
+}
+
+impl From<CustomReward> for FrontendReward {
+	fn from(value: CustomReward) -> Self {
+		Self {
+			manageable: is_manageable(value.id.as_str()),
+			id: value.id.to_string(),
+			title: value.title,
+			prompt: value.prompt,
+			cost: value.cost,
+			image: value.image.map(|img| img.url_4x),
+			default_image: value.default_image.map(|img| img.url_4x),
+			background_color: value.background_color,
+			is_enabled: value.is_enabled,
+			is_user_input_required: value.is_user_input_required,
+			max_per_stream: max_setting(&value.max_per_stream_setting),
+			max_per_user_per_stream: max_setting(&value.max_per_user_per_stream_setting),
+			global_cooldown_seconds: value
+				.global_cooldown_setting
+				.is_enabled
+				.then_some(value.global_cooldown_setting.global_cooldown_seconds as u64),
+			is_paused: value.is_paused,
+			is_in_stock: value.is_in_stock,
+			should_redemptions_skip_request_queue: value.should_redemptions_skip_request_queue,
+			redemptions_redeemed_current_stream: value.redemptions_redeemed_current_stream,
+			cooldown_expires_at: value.cooldown_expires_at.map(|t| t.to_string()),
+		}
+	}
+}
+
+/// Settings for creating or updating a reward, fields that are None stay untouched.
+/// For the limits and the cooldown, 0 turns them off.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Type)]
+pub struct RewardSettings {
+	pub title: Option<String>,
+	pub prompt: Option<String>,
+	pub cost: Option<usize>,
+	pub background_color: Option<String>,
+	pub is_enabled: Option<bool>,
+	pub is_paused: Option<bool>,
+	pub is_user_input_required: Option<bool>,
+	pub max_per_stream: Option<usize>,
+	pub max_per_user_per_stream: Option<usize>,
+	pub global_cooldown_seconds: Option<usize>,
+	pub should_redemptions_skip_request_queue: Option<bool>,
+}
+
+pub fn is_manageable(reward_id: &str) -> bool {
+	MANAGEABLE_REWARDS.contains(reward_id)
+}
+
 impl TwitchClient {
 	pub async fn update_redeems(&self) -> Result<Vec<CustomReward>, Error> {
 		if !CONFIG.read().enable_redeems.unwrap_or(true) {
@@ -35,15 +153,97 @@ impl TwitchClient {
 			.get_all_custom_rewards(&info.id, false, user_token.as_ref())
 			.await?;
 
+		let manageable = self
+			.client
+			.get_all_custom_rewards(&info.id, true, user_token.as_ref())
+			.await?;
+		MANAGEABLE_REWARDS.clear();
+		manageable.iter().for_each(|reward| {
+			MANAGEABLE_REWARDS.insert(reward.id.as_str().into());
+		});
+
 		redeems
 			.iter()
 			.for_each(|redeem| tracing::debug!("redeem '{}: {}'", redeem.title, redeem.cost));
 		Ok(redeems)
 	}
 
-	pub async fn sub_new_redeems(&self) -> Result<(), Error> {
-		if !CONFIG.read().enable_redeems.unwrap_or(true) {
-			return Ok(());
+	/// Fetches the rewards again and keeps them around
+	pub async fn refresh_redeems(&mut self) -> Result<(), Error> {
+		let redeems = self.update_redeems().await?;
+
+		// keep the titles up to date, they are needed to relink the action if the reward is re-created
+		redeems
+			.iter()
+			.for_each(|reward| set_reward_title(reward.id.as_str(), reward.title.as_str()));
+
+		self.redeems = Some(redeems);
+		Ok(())
+	}
+
+	/// Title and cost are required, everything else falls back to the twitch defaults
+	pub async fn create_reward(&self, settings: RewardSettings) -> Result<CustomReward, Error> {
+		let (title, cost) = match (settings.title, settings.cost) {
+			(Some(title), Some(cost)) => (title, cost),
+			_ => return Err(Error::from("A new reward needs a title and a cost")),
+		};
+
+		let user_token = match &self.token {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+
+		let info = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i,
+		};
+
+		let req = CreateCustomRewardRequest::broadcaster_id(info.id.clone());
+		let mut body = CreateCustomRewardBody::new(title, cost);
+		body.prompt = settings.prompt.map(Into::into);
+		body.background_color = settings.background_color.map(Into::into);
+		body.is_enabled = settings.is_enabled;
+		body.is_user_input_required = settings.is_user_input_required;
+		// twitch only accepts values of at least 1, 0 just turns the limit off
+		if let Some(max) = settings.max_per_stream {
+			body.is_max_per_stream_enabled = Some(max > 0);
+			body.max_per_stream = (max > 0).then_some(max);
+		}
+		if let Some(max) = settings.max_per_user_per_stream {
+			body.is_max_per_user_per_stream_enabled = Some(max > 0);
+			body.max_per_user_per_stream = (max > 0).then_some(max);
+		}
+		if let Some(cooldown) = settings.global_cooldown_seconds {
+			body.is_global_cooldown_enabled = Some(cooldown > 0);
+			body.global_cooldown_seconds = (cooldown > 0).then_some(cooldown);
+		}
+		body.should_redemptions_skip_request_queue = settings.should_redemptions_skip_request_queue;
+
+		let mut reward = self
+			.client
+			.req_post(req, body, user_token.as_ref())
+			.await?
+			.data;
+
+		// we created it, so we can manage it
+		MANAGEABLE_REWARDS.insert(reward.id.as_str().into());
+
+		// twitch only allows pausing existing rewards
+		if settings.is_paused == Some(true) {
+			let paused = RewardSettings {
+				is_paused: Some(true),
+				..Default::default()
+			};
+			self.update_reward(reward.id.as_str(), paused).await?;
+			reward.is_paused = true;
+		}
+
+		Ok(reward)
+	}
+
+	pub async fn update_reward(&self, id: &str, settings: RewardSettings) -> Result<(), Error> {
+		if !is_manageable(id) {
+			return Err(Error::new(ErrorMsg::RewardNotManageable));
 		}
 
 		let user_token = match &self.token {
@@ -56,36 +256,277 @@ impl TwitchClient {
 			Some(i) => i,
 		};
 
-		let ws_id = match &self.websocket_id {
-			None => return Err(Error::new(ErrorMsg::WebSocketSetup)),
-			Some(w) => w,
+		let req = UpdateCustomRewardRequest::new(info.id.clone(), id);
+		let mut body = UpdateCustomRewardBody::default();
+		body.title = settings.title.map(Into::into);
+		body.prompt = settings.prompt.map(Into::into);
+		body.cost = settings.cost;
+		body.background_color = settings.background_color.map(Into::into);
+		body.is_enabled = settings.is_enabled;
+		body.is_paused = settings.is_paused;
+		body.is_user_input_required = settings.is_user_input_required;
+		// twitch only accepts values of at least 1, 0 just turns the limit off
+		if let Some(max) = settings.max_per_stream {
+			body.is_max_per_stream_enabled = Some(max > 0);
+			body.max_per_stream = (max > 0).then_some(max);
+		}
+		if let Some(max) = settings.max_per_user_per_stream {
+			body.is_max_per_user_per_stream_enabled = Some(max > 0);
+			body.max_per_user_per_stream = (max > 0).then_some(max);
+		}
+		if let Some(cooldown) = settings.global_cooldown_seconds {
+			body.is_global_cooldown_enabled = Some(cooldown > 0);
+			body.global_cooldown_seconds = (cooldown > 0).then_some(cooldown);
+		}
+		body.should_redemptions_skip_request_queue = settings.should_redemptions_skip_request_queue;
+
+		self.client
+			.req_patch(req, body, user_token.as_ref())
+			.await?;
+
+		Ok(())
+	}
+
+	pub async fn delete_reward(&self, id: &str) -> Result<(), Error> {
+		if !is_manageable(id) {
+			return Err(Error::new(ErrorMsg::RewardNotManageable));
+		}
+
+		let user_token = match &self.token {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+
+		let info = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i,
+		};
+
+		let req = DeleteCustomRewardRequest::new(info.id.clone(), id);
+		self.client.req_delete(req, user_token.as_ref()).await?;
+
+		MANAGEABLE_REWARDS.remove(id);
+
+		Ok(())
+	}
+
+	pub async fn sub_new_redeems(&self) -> Result<(), Error> {
+		if !CONFIG.read().enable_redeems.unwrap_or(true) {
+			return Ok(());
+		}
+
+		let info = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i,
 		};
 
 		let event = ChannelPointsCustomRewardRedemptionAddV1::broadcaster_user_id(info.id.clone());
 
-		let trans = Transport::websocket(ws_id);
+		self.subscribe_for(Feature::Redeems, event).await
+	}
 
-		let sub = self
-			.client
-			.create_eventsub_subscription(event, trans, user_token.as_ref())
-			.await?;
+	/// Marks a redemption as fulfilled, or cancels it which refunds the points.
+	/// Twitch only allows this for rewards that were created with our client id.
+	pub async fn update_redemption_status(
+		&self,
+		reward_id: &str,
+		redemption_id: &str,
+		status: CustomRewardRedemptionStatus,
+	) -> Result<(), Error> {
+		let user_token = match &self.token {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+
+		let info = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i,
+		};
+
+		let req = UpdateRedemptionStatusRequest::new(info.id.clone(), reward_id, redemption_id);
+		let body = UpdateRedemptionStatusBody::status(status);
 
-		// do we need this response?
-		tracing::debug!("Setup Redeem Subscription: {sub:#?}");
+		self.client
+			.req_patch(req, body, user_token.as_ref())
+			.await?;
 
 		Ok(())
 	}
 }
 
-pub async fn exec_redeem(chatter_name: &str, id: &str, prompt: &str) {
-	if let Some(mut action) = get_action(id).await
-		&& action.allow_use()
-		&& let Trigger::Redeem(_) = action.trigger
+pub fn on_redemption(event: ChannelPointsCustomRewardRedemptionAddV1Payload) {
+	tracing::info!(
+		"{} redeemed: {} ({}); {}",
+		event.user_name,
+		event.reward.title,
+		event.reward.id,
+		event.user_input
+	);
+
+	spawn(handle_redemption(PendingRedemption::from(&event)));
+}
+
+/// Runs the action of a redemption and applies its redeem policy
+pub async fn handle_redemption(item: PendingRedemption) {
+	if let Some(action) =
+		get_redeem_action(item.reward_id.as_ref(), item.reward_title.as_ref()).await
+		&& action.redeem_policy == RedeemPolicy::Review
 	{
-		let prompt = match prompt.len() {
-			0 => None,
-			_ => Some(prompt),
+		enqueue(item);
+		return;
+	}
+
+	let outcome = match exec_redeem(
+		item.user.as_ref(),
+		item.reward_id.as_ref(),
+		item.reward_title.as_ref(),
+		item.prompt.as_ref(),
+		item.simulated,
+	)
+	.await
+	{
+		None => return, // no action for this reward, leave it to the streamer
+		Some(o) => o,
+	};
+
+	let status = match (outcome.policy, outcome.success) {
+		(RedeemPolicy::Manual | RedeemPolicy::Review, _) => return,
+		(RedeemPolicy::AutoFulfill | RedeemPolicy::RefundOnFailure, true) => {
+			CustomRewardRedemptionStatus::Fulfilled
+		}
+		(RedeemPolicy::AutoFulfill, false) => return, // failures stay in the queue for review
+		(RedeemPolicy::RefundOnFailure, false) => CustomRewardRedemptionStatus::Canceled,
+	};
+
+	if let Err(e) = set_redemption_status(&item, status).await
+		&& e.msg != ErrorMsg::RewardNotManageable
+	{
+		tracing::error!("Couldn't update redemption status: {e}");
+	}
+}
+
+/// Updates the status of a redemption, if the reward belongs to us
+pub async fn set_redemption_status(
+	item: &PendingRedemption,
+	status: CustomRewardRedemptionStatus,
+) -> Result<(), Error> {
+	if item.simulated {
+		simulator::note(format!(
+			"Would set redemption of {} by {} to {status:?}",
+			item.reward_title, item.user
+		));
+		return Ok(());
+	}
+
+	if item.skipped_queue {
+		return match status {
+			CustomRewardRedemptionStatus::Fulfilled => Ok(()),
+			_ => Err(Error::from_str(
+				"The reward skips the request queue, its redemptions can't be refunded",
+				ErrorMsg::RewardNotManageable,
+			)),
+		};
+	}
+
+	if !is_manageable(item.reward_id.as_ref()) {
+		tracing::warn!(
+			"Reward {} wasn't created by {NAME_CAPITALIZED}, so its redemptions can't be updated",
+			item.reward_title
+		);
+		return Err(Error::new(ErrorMsg::RewardNotManageable));
+	}
+
+	tracing::debug!("Setting redemption {} to {status:?}", item.id);
+
+	TWITCH_CLIENT
+		.read()
+		.await
+		.update_redemption_status(item.reward_id.as_ref(), item.id.as_ref(), status)
+		.await
+}
+
+/// Runs the action linked to the reward.
+/// Returns None if there is no action for it.
+pub async fn exec_redeem(
+	chatter_name: &str,
+	id: &str,
+	title: &str,
+	prompt: &str,
+	simulated: bool,
+) -> Option<RedeemOutcome> {
+	let mut action = get_redeem_action(id, title).await?;
+
+	let policy = action.redeem_policy;
+
+	let prompt = match prompt.len() {
+		0 => None,
+		_ => Some(prompt),
+	};
+	let ctx = ExecCtx {
+		simulated,
+		..ExecCtx::new(chatter_name, prompt)
+	};
+
+	// the points are already spent, so a redeem on cooldown counts as failed
+	if !action.allow_use(simulated) {
+		action.skip(&ctx, "On cooldown or disabled");
+		return Some(RedeemOutcome {
+			success: false,
+			policy,
+		});
+	}
+
+	let success = action.run(&ctx).await.is_ok();
+
+	Some(RedeemOutcome { success, policy })
+}
+
+/// Redeem actions whose reward doesn't exist anymore
+pub async fn orphaned_redeem_actions(rewards: &[CustomReward]) -> Vec<Action> {
+	get_all_actions(None)
+		.await
+		.into_iter()
+		.filter(|action| match &action.trigger {
+			Trigger::Redeem(id) => !rewards.iter().any(|r| r.id.as_str() == id.deref()),
+			_ => false,
+		})
+		.collect()
+}
+
+/// Links orphaned redeem actions to the current reward with the remembered title.
+/// Rewards that already have an action or titles that are ambiguous are left alone.
+/// Returns the old and new reward ids.
+pub async fn relink_redeem_actions(rewards: &[CustomReward]) -> Vec<(String, String)> {
+	let mut relinked = Vec::new();
+
+	for action in orphaned_redeem_actions(rewards).await {
+		let title = match &action.reward_title {
+			None => continue,
+			Some(t) => t,
 		};
-		action.exec.exec(chatter_name, prompt).await;
+
+		let mut matching = rewards
+			.iter()
+			.filter(|r| r.title.eq_ignore_ascii_case(title));
+		let reward = match (matching.next(), matching.next()) {
+			(Some(r), None) => r,
+			_ => continue,
+		};
+
+		let taken = get_all_actions(None)
+			.await
+			.iter()
+			.any(|a| matches!(&a.trigger, Trigger::Redeem(id) if id.deref() == reward.id.as_str()));
+		if taken {
+			continue;
+		}
+
+		let old = action.trigger.deref().to_string();
+		let new_trigger = Trigger::Redeem(ArcStr::from(reward.id.as_str()));
+		if relink_action(old.as_str(), new_trigger).await.is_some() {
+			relinked.push((old, reward.id.to_string()));
+		}
 	}
+
+	relinked
 }
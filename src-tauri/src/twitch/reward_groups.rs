@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use tokio::spawn;
+
+use crate::{
+	config::{CONFIG, RewardGroup},
+	twitch::{
+		TWITCH_CLIENT,
+		redeems::{RewardSettings, is_manageable},
+		stream::get_stream_session,
+	},
+};
+
+/// Groups paused from chat or the UI, they stay paused until resumed again, even across restarts
+struct ManualPause {
+	all: bool,
+	groups: Vec<Box<str>>,
+}
+
+impl ManualPause {
+	fn read() -> Self {
+		let config = CONFIG.read();
+		Self {
+			all: config.redeems_paused.unwrap_or(false),
+			groups: config.paused_reward_groups.clone().unwrap_or_default(),
+		}
+	}
+}
+
+impl RewardGroup {
+	fn should_pause(&self, manual: &ManualPause, live: bool, category: &str) -> bool {
+		if manual.all || manual.groups.contains(&self.name) {
+			return true;
+		}
+
+		if self.live_only && !live {
+			return true;
+		}
+
+		!self.categories.is_empty()
+			&& !self
+				.categories
+				.iter()
+				.any(|c| c.eq_ignore_ascii_case(category))
+	}
+}
+
+pub fn get_reward_groups() -> Vec<RewardGroup> {
+	CONFIG.read().reward_groups.clone().unwrap_or_default()
+}
+
+/// Pauses or resumes the rewards of every group, depending on the stream state and category.
+/// Only rewards created by the bot can be changed.
+pub async fn apply_reward_groups() {
+	if !CONFIG.read().enable_redeems.unwrap_or(true) {
+		return;
+	}
+
+	let groups = get_reward_groups();
+	if groups.is_empty() {
+		return;
+	}
+
+	let manual = ManualPause::read();
+	let session = get_stream_session();
+	let live = session.is_some();
+	let category = session.map(|s| s.category).unwrap_or_default();
+
+	// a reward in several groups stays enabled as long as one of them wants it
+	let mut wanted: HashMap<Box<str>, bool> = HashMap::new();
+	for group in &groups {
+		let paused = group.should_pause(&manual, live, category.as_ref());
+		for id in &group.rewards {
+			wanted
+				.entry(id.clone())
+				.and_modify(|p| *p &= paused)
+				.or_insert(paused);
+		}
+	}
+
+	let mut changed = false;
+	{
+		let client = TWITCH_CLIENT.read().await;
+		let rewards = client.get_redeems().unwrap_or_default();
+
+		for (id, paused) in wanted {
+			match rewards.iter().find(|r| r.id.as_str() == id.as_ref()) {
+				None => {
+					tracing::warn!("Reward {id} of a reward group doesn't exist anymore");
+					continue;
+				}
+				Some(r) if r.is_paused == paused => continue,
+				Some(_) => {}
+			}
+
+			if !is_manageable(id.as_ref()) {
+				tracing::warn!("Reward {id} of a reward group can't be paused by the bot");
+				continue;
+			}
+
+			let settings = RewardSettings {
+				is_paused: Some(paused),
+				..Default::default()
+			};
+
+			match client.update_reward(id.as_ref(), settings).await {
+				Err(e) => tracing::error!("Couldn't update reward {id}: {e}"),
+				Ok(()) => {
+					tracing::debug!("Set reward {id} paused: {paused}");
+					changed = true;
+				}
+			}
+		}
+	}
+
+	if changed && let Err(e) = TWITCH_CLIENT.write().await.refresh_redeems().await {
+		tracing::warn!("Couldn't refresh rewards: {e}");
+	}
+}
+
+/// Whether the rewards of the group should be paused right now.
+/// None if there is no group with that name.
+fn is_group_paused(name: &str) -> Option<bool> {
+	let group = get_reward_groups()
+		.into_iter()
+		.find(|g| g.name.eq_ignore_ascii_case(name))?;
+
+	let session = get_stream_session();
+	let live = session.is_some();
+	let category = session.map(|s| s.category).unwrap_or_default();
+	Some(group.should_pause(&ManualPause::read(), live, category.as_ref()))
+}
+
+/// Stream state or category changed
+pub fn on_stream_change() {
+	spawn(apply_reward_groups());
+}
+
+/// Pauses or resumes a group, or all of them if no name is given.
+/// Returns false if there is no group with that name.
+pub async fn set_paused(group: Option<&str>, paused: bool) -> bool {
+	{
+		let mut config = CONFIG.write();
+		match group {
+			None => {
+				config.redeems_paused = Some(paused);
+				if !paused {
+					config.paused_reward_groups = Some(Vec::new());
+				}
+			}
+			Some(name) => {
+				let group = config
+					.reward_groups
+					.iter()
+					.flatten()
+					.find(|g| g.name.eq_ignore_ascii_case(name));
+
+				let name = match group {
+					None => return false,
+					Some(g) => g.name.clone(),
+				};
+
+				let paused_groups = config.paused_reward_groups.get_or_insert_default();
+				paused_groups.retain(|g| *g != name);
+				if paused {
+					paused_groups.push(name);
+				}
+			}
+		}
+
+		if let Err(e) = config.save() {
+			tracing::warn!("Couldn't save the paused reward groups: {e}");
+		}
+	}
+
+	apply_reward_groups().await;
+	true
+}
+
+/// `!redeems off|on [group]`, returns the reply for chat
+pub async fn chat_command(args: &str) -> String {
+	let mut split = args.split_whitespace();
+	let paused = match split.next() {
+		Some("off") => true,
+		Some("on") => false,
+		_ => return String::from("Usage: !redeems off|on [group]"),
+	};
+
+	let group = match split.collect::<Vec<_>>().join(" ") {
+		name if name.is_empty() => None,
+		name => Some(name),
+	};
+
+	let state = match paused {
+		true => "paused",
+		false => "resumed",
+	};
+
+	match group {
+		None => {
+			set_paused(None, paused).await;
+			format!("All redeem groups {state}")
+		}
+		Some(name) => {
+			if !set_paused(Some(name.as_str()), paused).await {
+				return format!("There is no redeem group {name}");
+			}
+
+			// resuming one group doesn't lift the other reasons to pause it
+			match (paused, is_group_paused(name.as_str())) {
+				(false, Some(true)) if ManualPause::read().all => {
+					format!(
+						"Redeem group {name} stays paused, all redeems are paused. Use !redeems on"
+					)
+				}
+				(false, Some(true)) => {
+					format!("Redeem group {name} stays paused, it doesn't fit the stream right now")
+				}
+				_ => format!("Redeem group {name} {state}"),
+			}
+		}
+	}
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use twitch_oauth2::{Scope, TwitchToken, UserToken};
+
+use crate::{config::Config, twitch::TwitchClient};
+
+/// Parts of the bot that need extra scopes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum Feature {
+	Chat,
+	Redeems,
+	Moderation,
+	Chatters,
+	Subscriptions,
+	Follows,
+	Bits,
+}
+
+impl Feature {
+	pub const ALL: [Feature; 7] = [
+		Feature::Chat,
+		Feature::Redeems,
+		Feature::Moderation,
+		Feature::Chatters,
+		Feature::Subscriptions,
+		Feature::Follows,
+		Feature::Bits,
+	];
+
+	pub fn scopes(self) -> Vec<Scope> {
+		match self {
+			Feature::Chat => vec![Scope::ChatEdit, Scope::ChatRead],
+			Feature::Redeems => vec![
+				Scope::ChannelReadRedemptions,
+				Scope::ChannelManageRedemptions,
+			],
+			Feature::Moderation => vec![
+				Scope::ModeratorReadBannedUsers,
+				Scope::ModeratorManageBannedUsers,
+			],
+			Feature::Chatters => vec![Scope::ModeratorReadChatters, Scope::ModeratorReadVips],
+			Feature::Subscriptions => vec![Scope::ChannelReadSubscriptions],
+			Feature::Follows => vec![Scope::ModeratorReadFollowers],
+			Feature::Bits => vec![Scope::BitsRead],
+		}
+	}
+}
+
+/// Scopes the bot asks for, that the token wasn't granted
+pub fn missing_scopes(token: &UserToken) -> Vec<Scope> {
+	// unwrap is safe here, default Config has scopes
+	Config::default()
+		.scopes
+		.unwrap()
+		.into_iter()
+		.filter(|scope| !token.scopes().contains(scope))
+		.collect()
+}
+
+/// Scopes the token already has plus the missing ones
+pub fn upgraded_scopes(token: &UserToken) -> Vec<Scope> {
+	let mut scopes = token.scopes().to_vec();
+	scopes.extend(missing_scopes(token));
+	scopes
+}
+
+impl TwitchClient {
+	pub fn has_feature(&self, feature: Feature) -> bool {
+		match &self.token {
+			None => false,
+			Some(token) => feature
+				.scopes()
+				.iter()
+				.all(|scope| token.scopes().contains(scope)),
+		}
+	}
+
+	/// Features the current login doesn't have the scopes for
+	pub fn disabled_features(&self) -> Vec<Feature> {
+		Feature::ALL
+			.into_iter()
+			.filter(|feature| !self.has_feature(*feature))
+			.collect()
+	}
+}
@@ -0,0 +1,212 @@
+use std::{
+	ops::Deref,
+	sync::{
+		LazyLock,
+		atomic::{AtomicBool, Ordering},
+		nonpoison::Mutex,
+	},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri_specta::Event;
+
+use crate::{
+	config::ARGS,
+	error::Error,
+	twitch::{
+		TWITCH_CLIENT,
+		actions::{self, ExecCtx, Trigger},
+		chat::{handle_chat, is_privileged_badge},
+		notifications::fire,
+		redeem_queue::{self, PendingRedemption},
+		redeems::handle_redemption,
+		stream::uptime,
+	},
+	utils::{NAME_CAPITALIZED, emit_event, get_unix, get_unix_milli},
+};
+
+// anything bigger only gets dropped from the front
+const MAX_TRANSCRIPT_LEN: usize = 500;
+
+static SIMULATING: LazyLock<AtomicBool> =
+	LazyLock::new(|| AtomicBool::new(ARGS.simulate || !ARGS.simulate_chat.is_empty()));
+static TRANSCRIPT: LazyLock<Mutex<Vec<TranscriptLine>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum TranscriptKind {
+	/// Injected chat message
+	Incoming,
+	/// What the bot said
+	Outgoing,
+	/// Something that would have happened on twitch, e.g. a timeout
+	Note,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct TranscriptLine {
+	pub at: u64,
+	pub user: Box<str>,
+	pub text: Box<str>,
+	pub kind: TranscriptKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct TranscriptEvent {
+	pub line: TranscriptLine,
+}
+
+/// Whether the simulator is open. Real chat and events keep working,
+/// only what is injected through the simulator stays in the transcript.
+pub fn is_simulating() -> bool {
+	SIMULATING.load(Ordering::Relaxed)
+}
+
+pub fn set_simulating(simulating: bool) {
+	tracing::info!("Simulation mode: {simulating}");
+	SIMULATING.store(simulating, Ordering::Relaxed);
+
+	if !simulating {
+		redeem_queue::drop_simulated();
+		actions::drop_simulated_counters();
+	}
+}
+
+pub fn get_transcript() -> Vec<TranscriptLine> {
+	TRANSCRIPT.lock().clone()
+}
+
+pub fn clear_transcript() {
+	TRANSCRIPT.lock().clear();
+}
+
+fn record(user: &str, text: String, kind: TranscriptKind) {
+	let line = TranscriptLine {
+		at: get_unix_milli(),
+		user: Box::from(user),
+		text: text.into_boxed_str(),
+		kind,
+	};
+
+	{
+		let mut transcript = TRANSCRIPT.lock();
+		if transcript.len() >= MAX_TRANSCRIPT_LEN {
+			transcript.remove(0);
+		}
+		transcript.push(line.clone());
+	}
+
+	emit_event(TranscriptEvent { line });
+}
+
+pub fn record_outgoing(msg: String) {
+	record(NAME_CAPITALIZED, msg, TranscriptKind::Outgoing);
+}
+
+pub fn note(text: String) {
+	tracing::info!("Simulated: {text}");
+	record(NAME_CAPITALIZED, text, TranscriptKind::Note);
+}
+
+/// Runs a message through the same path as real chat
+pub async fn simulate_chat(user: &str, text: &str, badges: &[String]) -> Result<(), Error> {
+	record(user, text.to_string(), TranscriptKind::Incoming);
+
+	let privileged = badges.iter().any(|badge| is_privileged_badge(badge));
+	handle_chat(user, text, privileged, true).await
+}
+
+/// Injects the chat messages passed on the command line
+pub async fn replay_cli_chat() {
+	for line in &ARGS.simulate_chat {
+		let (user, text) = match line.split_once(':') {
+			None => {
+				tracing::warn!("Ignoring simulated chat line without a user: {line}");
+				continue;
+			}
+			Some((user, text)) => (user.trim(), text.trim()),
+		};
+
+		if let Err(e) = simulate_chat(user, text, &[]).await {
+			tracing::error!("Error simulating chat message: {e}");
+		}
+	}
+}
+
+/// Redeems a reward, found by id or title.
+/// Unknown rewards are still redeemed, so actions can be tested without being logged in.
+pub async fn simulate_redeem(user: &str, reward: &str, prompt: &str) {
+	let (reward_id, reward_title) = {
+		let client = TWITCH_CLIENT.read().await;
+		let found = client
+			.get_redeems()
+			.unwrap_or_default()
+			.iter()
+			.find(|r| r.id.as_str() == reward || r.title.eq_ignore_ascii_case(reward));
+
+		match found {
+			None => (Box::from(reward), Box::from(reward)),
+			Some(r) => (r.id.as_str().into(), r.title.as_str().into()),
+		}
+	};
+
+	note(format!("{user} redeemed {reward_title}: {prompt}"));
+
+	handle_redemption(PendingRedemption {
+		id: format!("simulated-{}", get_unix_milli()).into(),
+		reward_id,
+		reward_title,
+		user: Box::from(user),
+		prompt: Box::from(prompt),
+		redeemed_at: get_unix(),
+		simulated: true,
+		skipped_queue: false,
+	})
+	.await;
+}
+
+/// Fires an event trigger, `amount` fills in bits, viewers, months or the number of gifted subs
+pub async fn simulate_event(
+	trigger: Trigger,
+	user: &str,
+	prompt: Option<&str>,
+	amount: Option<u64>,
+) -> Result<(), Error> {
+	let amount = amount.unwrap_or(1);
+
+	let ctx = ExecCtx::new(user, prompt).simulated();
+	let ctx = match &trigger {
+		Trigger::Command(cmd) => {
+			let text = match prompt {
+				None => format!("!{}", cmd.deref()),
+				Some(p) => format!("!{} {p}", cmd.deref()),
+			};
+			return simulate_chat(user, text.as_str(), &[]).await;
+		}
+		Trigger::Redeem(reward) => {
+			simulate_redeem(user, reward, prompt.unwrap_or_default()).await;
+			return Ok(());
+		}
+		Trigger::Follow | Trigger::StreamStart => ctx,
+		Trigger::Subscribe => ctx.with_var("tier", "1"),
+		Trigger::Resub => ctx
+			.with_var("tier", "1")
+			.with_var("months", amount)
+			.with_var("streak", amount),
+		Trigger::GiftSub => ctx
+			.with_var("tier", "1")
+			.with_var("amount", amount)
+			.with_var("total", amount),
+		Trigger::Cheer => ctx.with_var("bits", amount),
+		Trigger::Raid => ctx.with_var("viewers", amount),
+		Trigger::StreamEnd => ctx
+			.with_var("uptime", uptime().unwrap_or_else(|| String::from("0m")))
+			.with_var("peak_viewers", amount)
+			.with_var("title", "Simulated stream")
+			.with_var("category", "Just Chatting"),
+	};
+
+	note(format!("Simulated {} from {user}", trigger.deref()));
+	fire(trigger, ctx);
+	Ok(())
+}
@@ -0,0 +1,271 @@
+use std::{
+	sync::{LazyLock, nonpoison::Mutex, nonpoison::RwLock},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri_specta::Event;
+use tokio::{spawn, task::JoinHandle, time::interval};
+use twitch_api::{
+	eventsub::{
+		channel::{ChannelUpdateV2, ChannelUpdateV2Payload},
+		stream::{StreamOfflineV1, StreamOfflineV1Payload, StreamOnlineV1, StreamOnlineV1Payload},
+	},
+	helix::streams::{GetStreamsRequest, Stream},
+};
+
+use crate::{
+	error::{Error, ErrorMsg},
+	twitch::{
+		TWITCH_CLIENT, TwitchClient, action_groups,
+		actions::{ExecCtx, Trigger, reset_stream_counters},
+		chat::reset_stream_chatters,
+		notifications::fire,
+		reward_groups,
+	},
+	utils::{emit_event, get_unix},
+};
+
+const VIEWER_POLL_INTERVAL: Duration = Duration::from_secs(120);
+
+static STREAM_SESSION: LazyLock<RwLock<Option<StreamSession>>> =
+	LazyLock::new(|| RwLock::new(None));
+static VIEWER_POLLER: LazyLock<Mutex<Option<JoinHandle<()>>>> = LazyLock::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct StreamSession {
+	pub started_at: u64,
+	pub title: Box<str>,
+	pub category: Box<str>,
+	pub peak_viewers: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct StreamSessionEvent {
+	pub session: Option<StreamSession>,
+}
+
+impl StreamSession {
+	fn from_stream(stream: &Stream) -> Self {
+		Self {
+			started_at: parse_timestamp(stream.started_at.as_str()).unwrap_or_else(get_unix),
+			title: stream.title.as_str().into(),
+			category: stream.game_name.as_str().into(),
+			peak_viewers: stream.viewer_count,
+		}
+	}
+}
+
+impl TwitchClient {
+	pub async fn sub_stream_events(&self) -> Result<(), Error> {
+		let id = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i.id.clone(),
+		};
+
+		self.subscribe(StreamOnlineV1::broadcaster_user_id(id.clone()))
+			.await?;
+		self.subscribe(StreamOfflineV1::broadcaster_user_id(id.clone()))
+			.await?;
+		self.subscribe(ChannelUpdateV2::broadcaster_user_id(id))
+			.await?;
+
+		Ok(())
+	}
+
+	/// Returns the current stream, if the channel is live
+	pub async fn get_live_stream(&self) -> Result<Option<Stream>, Error> {
+		let user_token = match &self.token {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+
+		let info = match &self.user_info {
+			None => return Err(Error::new(ErrorMsg::UsernameGone)),
+			Some(i) => i,
+		};
+
+		let req = GetStreamsRequest::user_ids(vec![info.id.clone()]);
+		let streams = self.client.req_get(req, user_token.as_ref()).await?.data;
+
+		Ok(streams.into_iter().next())
+	}
+}
+
+fn parse_timestamp(s: &str) -> Option<u64> {
+	let time = chrono::DateTime::parse_from_rfc3339(s).ok()?;
+	u64::try_from(time.timestamp()).ok()
+}
+
+pub fn get_stream_session() -> Option<StreamSession> {
+	STREAM_SESSION.read().clone()
+}
+
+pub fn is_live() -> bool {
+	STREAM_SESSION.read().is_some()
+}
+
+/// Uptime of the current stream, e.g. `2h 5m`
+pub fn uptime() -> Option<String> {
+	let started_at = STREAM_SESSION.read().as_ref()?.started_at;
+	let secs = get_unix().saturating_sub(started_at);
+
+	let hours = secs / 3600;
+	let minutes = (secs % 3600) / 60;
+	match hours {
+		0 => Some(format!("{minutes}m")),
+		_ => Some(format!("{hours}h {minutes}m")),
+	}
+}
+
+fn set_session(session: Option<StreamSession>) {
+	*STREAM_SESSION.write() = session.clone();
+	emit_event(StreamSessionEvent { session });
+}
+
+/// Resets everything that only lives for one stream
+pub async fn reset_stream_state() {
+	reset_stream_counters().await;
+	reset_stream_chatters().await;
+}
+
+/// Picks up a stream that was already running when the bot started.
+/// Doesn't fire any triggers or reset anything, since the stream didn't just start.
+pub async fn sync_stream_session() {
+	let res = TWITCH_CLIENT.read().await.get_live_stream().await;
+	match res {
+		Err(e) => tracing::warn!("Couldn't check if the stream is live: {e}"),
+		Ok(None) => {
+			if is_live() {
+				stop_viewer_poller();
+				set_session(None);
+			}
+			reward_groups::on_stream_change();
+		}
+		Ok(Some(stream)) => {
+			if !is_live() {
+				tracing::info!("Stream is already live");
+				set_session(Some(StreamSession::from_stream(&stream)));
+				start_viewer_poller();
+			}
+			reward_groups::on_stream_change();
+		}
+	}
+}
+
+fn start_viewer_poller() {
+	let handle = spawn(async {
+		let mut poll = interval(VIEWER_POLL_INTERVAL);
+		loop {
+			poll.tick().await;
+
+			let stream = match TWITCH_CLIENT.read().await.get_live_stream().await {
+				Err(e) => {
+					tracing::warn!("Couldn't poll stream info: {e}");
+					continue;
+				}
+				Ok(None) => continue, // the offline event will end the session
+				Ok(Some(s)) => s,
+			};
+
+			let mut session = STREAM_SESSION.write();
+			if let Some(session) = session.as_mut() {
+				session.peak_viewers = session.peak_viewers.max(stream.viewer_count);
+			}
+		}
+	});
+
+	if let Some(old) = VIEWER_POLLER.lock().replace(handle) {
+		old.abort();
+	}
+}
+
+fn stop_viewer_poller() {
+	if let Some(handle) = VIEWER_POLLER.lock().take() {
+		handle.abort();
+	}
+}
+
+pub fn on_online(event: StreamOnlineV1Payload) {
+	tracing::info!("{} went live", event.broadcaster_user_name);
+
+	let started_at = parse_timestamp(event.started_at.as_str()).unwrap_or_else(get_unix);
+
+	spawn(async move {
+		let stream = TWITCH_CLIENT.read().await.get_live_stream().await;
+		let session = match stream {
+			Ok(Some(stream)) => StreamSession {
+				started_at,
+				..StreamSession::from_stream(&stream)
+			},
+			res => {
+				if let Err(e) = res {
+					tracing::warn!("Couldn't get stream info: {e}");
+				}
+
+				// helix can lag behind eventsub, the poller and channel updates will fill this in
+				StreamSession {
+					started_at,
+					title: Box::from(""),
+					category: Box::from(""),
+					peak_viewers: 0,
+				}
+			}
+		};
+
+		reset_stream_state().await;
+		set_session(Some(session));
+		start_viewer_poller();
+		reward_groups::on_stream_change();
+		action_groups::on_stream_change(true);
+
+		fire(
+			Trigger::StreamStart,
+			ExecCtx::new(event.broadcaster_user_name.as_str(), None),
+		);
+	});
+}
+
+pub fn on_offline(event: StreamOfflineV1Payload) {
+	tracing::info!("{} went offline", event.broadcaster_user_name);
+
+	stop_viewer_poller();
+
+	let mut ctx = ExecCtx::new(event.broadcaster_user_name.as_str(), None);
+	if let Some(uptime) = uptime() {
+		ctx = ctx.with_var("uptime", uptime);
+	}
+	if let Some(session) = get_stream_session() {
+		ctx = ctx
+			.with_var("peak_viewers", session.peak_viewers)
+			.with_var("title", session.title)
+			.with_var("category", session.category);
+	}
+
+	set_session(None);
+	reward_groups::on_stream_change();
+	action_groups::on_stream_change(false);
+	fire(Trigger::StreamEnd, ctx);
+}
+
+pub fn on_channel_update(event: ChannelUpdateV2Payload) {
+	tracing::info!("Channel updated: {} ({})", event.title, event.category_name);
+
+	let session = {
+		let mut session = STREAM_SESSION.write();
+		match session.as_mut() {
+			None => return,
+			Some(session) => {
+				session.title = event.title.as_str().into();
+				session.category = event.category_name.as_str().into();
+				session.clone()
+			}
+		}
+	};
+
+	emit_event(StreamSessionEvent {
+		session: Some(session),
+	});
+	reward_groups::on_stream_change();
+}
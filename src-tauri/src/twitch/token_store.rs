@@ -0,0 +1,248 @@
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+	env,
+	fs::{self, OpenOptions},
+	io::Write,
+	path::PathBuf,
+	sync::{
+		LazyLock,
+		atomic::{AtomicBool, Ordering},
+		nonpoison::RwLock,
+	},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+	KeyInit, XChaCha20Poly1305, XNonce,
+	aead::{Aead, Payload},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	error::{Error, ErrorMsg},
+	utils::CFG_DIR_PATH,
+};
+
+// bump when the layout changes, it is also authenticated as associated data
+const MAGIC: &[u8] = b"BEANYBOT-TOKEN-1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+static STORE_PATH: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("token.enc"));
+
+// not a command line argument, those show up in the process list
+const PASSPHRASE_ENV: &str = "TOKEN_PASSPHRASE";
+
+// set from the frontend or the login prompt, if it isn't in the environment
+static PASSPHRASE: LazyLock<RwLock<Option<String>>> = LazyLock::new(|| RwLock::new(None));
+
+// the token couldn't be loaded or saved without a (correct) passphrase, so the frontend has to ask for one
+static PASSPHRASE_MISSING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize)]
+pub struct StoredToken {
+	pub access_token: String,
+	pub refresh_token: Option<String>,
+}
+
+pub fn set_passphrase(passphrase: String) {
+	*PASSPHRASE.write() = Some(passphrase);
+	PASSPHRASE_MISSING.store(false, Ordering::Relaxed);
+}
+
+pub fn passphrase_missing() -> bool {
+	PASSPHRASE_MISSING.load(Ordering::Relaxed)
+}
+
+fn passphrase() -> Result<String, Error> {
+	if let Ok(p) = env::var(PASSPHRASE_ENV)
+		&& !p.is_empty()
+	{
+		return Ok(p);
+	}
+
+	match PASSPHRASE.read().clone() {
+		None => {
+			PASSPHRASE_MISSING.store(true, Ordering::Relaxed);
+			Err(Error::new(ErrorMsg::PassphraseNeeded))
+		}
+		Some(p) => Ok(p),
+	}
+}
+
+pub fn exists() -> bool {
+	STORE_PATH.is_file()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], Error> {
+	let mut key = [0; 32];
+	Argon2::default()
+		.hash_password_into(passphrase.as_bytes(), salt, &mut key)
+		.map_err(|e| Error::from(format!("Couldn't derive token key: {e}")))?;
+	Ok(key)
+}
+
+/// Encrypts the token with a key derived from the passphrase.
+/// Layout: magic, salt, nonce, ciphertext
+fn encrypt(token: &StoredToken, passphrase: &str) -> Result<Vec<u8>, Error> {
+	let mut rng = rand::rng();
+	let mut salt = [0; SALT_LEN];
+	let mut nonce = [0; NONCE_LEN];
+	rng.fill(&mut salt);
+	rng.fill(&mut nonce);
+
+	let key = derive_key(passphrase, &salt)?;
+	let cipher = XChaCha20Poly1305::new(&key.into());
+
+	let plaintext = serde_json::to_vec(token)?;
+	let ciphertext = cipher
+		.encrypt(
+			XNonce::from_slice(&nonce),
+			Payload {
+				msg: plaintext.as_slice(),
+				aad: MAGIC,
+			},
+		)
+		.map_err(|_| Error::from("Couldn't encrypt token"))?;
+
+	let mut data = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+	data.extend_from_slice(MAGIC);
+	data.extend_from_slice(&salt);
+	data.extend_from_slice(&nonce);
+	data.extend_from_slice(&ciphertext);
+	Ok(data)
+}
+
+fn decrypt(data: &[u8], passphrase: &str) -> Result<StoredToken, Error> {
+	let rest = match data.strip_prefix(MAGIC) {
+		None => return Err(Error::from("Unknown token file format")),
+		Some(r) => r,
+	};
+
+	if rest.len() < SALT_LEN + NONCE_LEN {
+		return Err(Error::from("Token file is truncated"));
+	}
+	let (salt, rest) = rest.split_at(SALT_LEN);
+	let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+	let key = derive_key(passphrase, salt)?;
+	let cipher = XChaCha20Poly1305::new(&key.into());
+
+	// a wrong passphrase and a tampered file look the same here
+	let plaintext = cipher
+		.decrypt(
+			XNonce::from_slice(nonce),
+			Payload {
+				msg: ciphertext,
+				aad: MAGIC,
+			},
+		)
+		.map_err(|_| Error::from_str("Wrong passphrase", ErrorMsg::PassphraseNeeded))?;
+
+	Ok(serde_json::from_slice(plaintext.as_slice())?)
+}
+
+pub fn save(token: &StoredToken) -> Result<(), Error> {
+	let passphrase = passphrase()?;
+	let data = encrypt(token, passphrase.as_str())?;
+
+	fs::create_dir_all(CFG_DIR_PATH.as_path())?;
+
+	let mut options = OpenOptions::new();
+	options.create(true).write(true).truncate(true);
+	// nobody else gets to try guessing the passphrase
+	#[cfg(unix)]
+	options.mode(0o600);
+
+	let mut f = options.open(STORE_PATH.as_path())?;
+	// files saved before were created with the default permissions
+	#[cfg(unix)]
+	f.set_permissions(fs::Permissions::from_mode(0o600))?;
+	f.write_all(data.as_slice())?;
+
+	Ok(())
+}
+
+pub fn load() -> Result<StoredToken, Error> {
+	let data = fs::read(STORE_PATH.as_path())?;
+	let passphrase = passphrase()?;
+
+	decrypt(data.as_slice(), passphrase.as_str()).inspect_err(|e| {
+		if e.msg == ErrorMsg::PassphraseNeeded {
+			PASSPHRASE_MISSING.store(true, Ordering::Relaxed);
+		}
+	})
+}
+
+pub fn delete() -> Result<(), Error> {
+	if exists() {
+		fs::remove_file(STORE_PATH.as_path())?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn token() -> StoredToken {
+		StoredToken {
+			access_token: String::from("access"),
+			refresh_token: Some(String::from("refresh")),
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let data = encrypt(&token(), "hunter2").unwrap();
+		let loaded = decrypt(data.as_slice(), "hunter2").unwrap();
+
+		assert_eq!(loaded.access_token, "access");
+		assert_eq!(loaded.refresh_token.as_deref(), Some("refresh"));
+	}
+
+	#[test]
+	fn no_plaintext_in_file() {
+		let data = encrypt(&token(), "hunter2").unwrap();
+		assert!(!data.windows(6).any(|w| w == b"access"));
+		assert!(!data.windows(7).any(|w| w == b"refresh"));
+	}
+
+	#[test]
+	fn wrong_passphrase() {
+		let data = encrypt(&token(), "hunter2").unwrap();
+		let err = decrypt(data.as_slice(), "hunter3").err().unwrap();
+		assert_eq!(err.msg, ErrorMsg::PassphraseNeeded);
+	}
+
+	#[test]
+	fn tampered_file() {
+		let mut data = encrypt(&token(), "hunter2").unwrap();
+		if let Some(b) = data.last_mut() {
+			*b ^= 1;
+		}
+		assert!(decrypt(data.as_slice(), "hunter2").is_err());
+
+		// the salt and nonce are stored in the clear, changing them has to break the tag
+		for i in [MAGIC.len(), MAGIC.len() + SALT_LEN] {
+			let mut data = encrypt(&token(), "hunter2").unwrap();
+			if let Some(b) = data.get_mut(i) {
+				*b ^= 1;
+			}
+			let err = decrypt(data.as_slice(), "hunter2").err().unwrap();
+			assert_eq!(err.msg, ErrorMsg::PassphraseNeeded);
+		}
+
+		// an unknown header is rejected before decrypting
+		let data = encrypt(&token(), "hunter2").unwrap();
+		let rest = data.strip_prefix(MAGIC).unwrap();
+		let data = [b"BEANYBOT-TOKEN-2".as_slice(), rest].concat();
+		assert!(decrypt(data.as_slice(), "hunter2").is_err());
+
+		let mut data = encrypt(&token(), "hunter2").unwrap();
+		data.truncate(MAGIC.len() + SALT_LEN);
+		assert!(decrypt(data.as_slice(), "hunter2").is_err());
+	}
+}
@@ -0,0 +1,175 @@
+use std::{
+	cmp::Reverse,
+	fs::{self, create_dir_all, read_dir, remove_file},
+	ops::Deref,
+	path::{Path, PathBuf},
+	sync::LazyLock,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::interval;
+
+use crate::{
+	config::CONFIG,
+	error::Error,
+	twitch::actions::{Action, add_action, get_action, migrate_action},
+	utils::{ACTION_DIR, get_unix},
+};
+
+static TRASH_DIR: LazyLock<PathBuf> = LazyLock::new(|| ACTION_DIR.join("trash"));
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+// the bot may run for days without anything getting deleted
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A deleted action waiting in the trash
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct TrashedAction {
+	/// File name in the trash, used to restore it
+	pub id: String,
+	/// None if the file can't be read anymore
+	pub trigger: Option<String>,
+	/// Unix time in seconds
+	pub deleted_at: u64,
+}
+
+/// Moves an action file to the trash, prefixed with the current time
+pub fn move_to_trash(path: &Path) -> Result<(), Error> {
+	create_dir_all(TRASH_DIR.as_path())?;
+
+	let name = match path.file_name().and_then(|n| n.to_str()) {
+		None => return Err(Error::from(format!("Invalid action file {path:?}"))),
+		Some(n) => n,
+	};
+
+	let target = TRASH_DIR.join(format!("{}-{name}", get_unix()));
+	fs::rename(path, &target)?;
+	tracing::info!("Moved {path:?} to the trash");
+
+	purge_expired();
+	Ok(())
+}
+
+fn deleted_at(id: &str) -> Option<u64> {
+	id.split_once('-')?.0.parse().ok()
+}
+
+/// Only plain file names, so an id can't point outside the trash
+fn trash_path(id: &str) -> Result<PathBuf, Error> {
+	if Path::new(id).file_name().and_then(|n| n.to_str()) != Some(id) {
+		return Err(Error::from(format!("Invalid trash id {id}")));
+	}
+
+	let path = TRASH_DIR.join(id);
+	match path.is_file() {
+		false => Err(Error::from(format!("{id} is not in the trash"))),
+		true => Ok(path),
+	}
+}
+
+fn read_trashed(path: &Path) -> Result<(Action, Option<u32>), Error> {
+	let content = fs::read_to_string(path)?;
+	let table = content.parse::<toml::Table>()?;
+	migrate_action(table).map_err(|e| Error::from(e.to_string()))
+}
+
+/// Newest first
+pub fn list_trash() -> Result<Vec<TrashedAction>, Error> {
+	if !TRASH_DIR.is_dir() {
+		return Ok(Vec::new());
+	}
+
+	let mut trashed: Vec<TrashedAction> = read_dir(TRASH_DIR.as_path())?
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| path.is_file())
+		.filter_map(|path| {
+			let id = path.file_name()?.to_str()?.to_string();
+			let trigger = match read_trashed(&path) {
+				Err(e) => {
+					tracing::warn!("Couldn't read trashed action {id}: {e}");
+					None
+				}
+				Ok((action, _)) => Some(action.trigger.deref().to_string()),
+			};
+
+			Some(TrashedAction {
+				deleted_at: deleted_at(id.as_str()).unwrap_or_default(),
+				id,
+				trigger,
+			})
+		})
+		.collect();
+
+	trashed.sort_unstable_by_key(|t| Reverse(t.deleted_at));
+	Ok(trashed)
+}
+
+/// Puts the action back, unless its trigger was taken in the meantime.
+/// Returns the key of the restored action.
+pub async fn restore(id: &str) -> Result<String, Error> {
+	let path = trash_path(id)?;
+	let (action, _) = read_trashed(&path)?;
+
+	let key = action.trigger.deref().to_string();
+	if get_action(key.as_str()).await.is_some() {
+		return Err(Error::from(format!("There already is an action for {key}")));
+	}
+
+	// the trash file is the only copy, so it stays until the action is saved again
+	add_action(action).await?;
+	remove_file(path)?;
+	tracing::info!("Restored action {key} from the trash");
+
+	Ok(key)
+}
+
+/// Purges the trash on startup and then every hour
+pub async fn purge_periodically() {
+	let mut purge = interval(PURGE_INTERVAL);
+	loop {
+		purge.tick().await;
+		purge_expired();
+	}
+}
+
+/// Deletes everything older than the retention period, returns how many files were deleted
+pub fn purge_expired() -> usize {
+	let days = CONFIG.read().trash_retention_days.unwrap_or(30);
+	if days == 0 || !TRASH_DIR.is_dir() {
+		return 0;
+	}
+
+	let cutoff = get_unix().saturating_sub(u64::from(days) * DAY_SECS);
+
+	let entries = match read_dir(TRASH_DIR.as_path()) {
+		Err(e) => {
+			tracing::warn!("Couldn't read the trash: {e}");
+			return 0;
+		}
+		Ok(e) => e,
+	};
+
+	entries
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| {
+			path.file_name()
+				.and_then(|n| n.to_str())
+				.and_then(deleted_at)
+				.is_some_and(|at| at < cutoff)
+		})
+		.filter(|path| match remove_file(path) {
+			Err(e) => {
+				tracing::warn!("Couldn't purge {path:?} from the trash: {e}");
+				false
+			}
+			Ok(()) => {
+				tracing::info!("Purged {path:?} from the trash");
+				true
+			}
+		})
+		.count()
+}
@@ -1,58 +1,96 @@
 use std::sync::{Arc, LazyLock};
 
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use twitch_oauth2::TwitchToken;
 
-use crate::twitch::TwitchClient;
+use crate::{
+	error::{Error, ErrorMsg},
+	twitch::{TwitchClient, simulator},
+};
 
-pub enum BanResult {
-	Nope,
-	TimedOut,
-	Banned,
+/// What twitch reported back about a ban or timeout
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct BanResult {
+	pub user: Box<str>,
+	/// None in the simulation
+	pub user_id: Option<Box<str>>,
+	/// RFC 3339, None in the simulation
+	pub created_at: Option<Box<str>>,
+	/// RFC 3339, None for bans and in the simulation
+	pub end_time: Option<Box<str>>,
 }
 
 static USER_CACHE: LazyLock<DashMap<Arc<str>, Arc<str>>> = LazyLock::new(DashMap::new);
 
 impl TwitchClient {
-	async fn get_twitch_user_id(&self, username: &str) -> Option<Arc<str>> {
-		match USER_CACHE.get(username) {
-			Some(user_id) => Some(user_id.clone()),
-			None => {
-				let token = self.token.clone()?;
-				let usr = self
-					.client
-					.get_user_from_login(username, token.as_ref())
-					.await;
-				if let Ok(Some(usr)) = usr {
-					let id: Arc<str> = Arc::from(usr.id.as_str());
-					USER_CACHE.insert(Arc::from(usr.login.as_str()), id.clone());
-					Some(id)
-				} else {
-					None
-				}
+	async fn get_twitch_user_id(&self, username: &str) -> Result<Arc<str>, Error> {
+		if let Some(user_id) = USER_CACHE.get(username) {
+			return Ok(user_id.clone());
+		}
+
+		let token = match self.token.clone() {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+
+		let usr = self
+			.client
+			.get_user_from_login(username, token.as_ref())
+			.await?;
+
+		match usr {
+			None => Err(Error::from(format!("No twitch user called {username}"))
+				.overwrite_msg(ErrorMsg::UserNotFound)),
+			Some(usr) => {
+				let id: Arc<str> = Arc::from(usr.id.as_str());
+				USER_CACHE.insert(Arc::from(usr.login.as_str()), id.clone());
+				Ok(id)
 			}
 		}
 	}
 
-	/// Return Some<()> if the user was banned/timed out. Returns None if they weren't
+	/// Bans the user, or times them out if there is a duration.
+	/// Simulated bans only show up in the transcript.
 	pub async fn ban_user(
 		&self,
 		username: &str,
 		reason: &str,
 		duration: Option<u32>,
-	) -> Option<()> {
-		let token = self.token.clone()?;
-		let user_id = token.user_id()?;
+		simulated: bool,
+	) -> Result<BanResult, Error> {
+		if simulated {
+			match duration {
+				None => simulator::note(format!("Would ban {username}")),
+				Some(secs) => simulator::note(format!("Would timeout {username} for {secs}s")),
+			}
+			return Ok(BanResult {
+				user: Box::from(username),
+				user_id: None,
+				created_at: None,
+				end_time: None,
+			});
+		}
+
+		let token = match self.token.clone() {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(t) => t,
+		};
+		let user_id = match token.user_id() {
+			None => return Err(Error::new(ErrorMsg::TokenGone)),
+			Some(id) => id,
+		};
 
-		if username == token.login.as_str() {
-			return None;
+		if username.eq_ignore_ascii_case(token.login.as_str()) {
+			return Err(Error::new(ErrorMsg::CantBanSelf));
 		}
 
 		let id = self
 			.get_twitch_user_id(username.to_lowercase().as_str())
 			.await?;
 
-		_ = self
+		let ban = self
 			.client
 			.ban_user(
 				id.as_ref(),
@@ -63,8 +101,13 @@ impl TwitchClient {
 				token.as_ref(),
 			)
 			.await
-			.ok()?;
+			.map_err(|e| Error::from(e).overwrite_msg(ErrorMsg::Moderation))?;
 
-		Some(())
+		Ok(BanResult {
+			user: Box::from(username),
+			user_id: Some(Box::from(ban.user_id.as_str())),
+			created_at: Some(Box::from(ban.created_at.as_str())),
+			end_time: ban.end_time.map(|t| Box::from(t.as_str())),
+		})
 	}
 }
@@ -2,12 +2,14 @@ use std::{
 	env::current_dir,
 	ops::Deref,
 	path::PathBuf,
-	sync::LazyLock,
+	sync::{LazyLock, OnceLock},
 	time::{SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tauri::AppHandle;
+use tauri_specta::Event;
 
 pub static NAME: &str = env!("CARGO_PKG_NAME");
 pub static NAME_CAPITALIZED: &str = "Beanybot";
@@ -23,6 +25,24 @@ pub static CFG_DIR_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
 });
 
 pub static ACTION_DIR: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("actions"));
+// files actions refer to, bundles carry them along
+pub static ASSET_DIR: LazyLock<PathBuf> = LazyLock::new(|| CFG_DIR_PATH.join("assets"));
+
+// set once the tauri app is running, so the library can push events to the frontend
+pub static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+/// Emits an event to the frontend.
+/// Does nothing if the app isn't set up yet, e.g. while running from the CLI
+pub fn emit_event<E: Event + Serialize + Clone>(event: E) {
+	let handle = match APP_HANDLE.get() {
+		None => return,
+		Some(h) => h,
+	};
+
+	if let Err(e) = event.emit(handle) {
+		tracing::warn!("Couldn't emit {} event: {e}", E::NAME);
+	}
+}
 
 pub fn get_unix() -> u64 {
 	let now = SystemTime::now();
@@ -25,18 +25,118 @@ async logout() : Promise<void> {
 async getAccentColor() : Promise<ColorSchemeAccent | null> {
     return await TAURI_INVOKE("get_accent_color");
 },
-async getAllActions() : Promise<Action[]> {
-    return await TAURI_INVOKE("get_all_actions");
+async getAllActions(tag: string | null) : Promise<Action[]> {
+    return await TAURI_INVOKE("get_all_actions", { tag });
 },
-async addAction(action: Action) : Promise<void> {
-    await TAURI_INVOKE("add_action", { action });
+async getActionTags() : Promise<string[]> {
+    return await TAURI_INVOKE("get_action_tags");
+},
+/**
+ * Enables or disables every action with the tag, returns how many have it
+ */
+async setActionGroupDisabled(tag: string, disabled: boolean) : Promise<number> {
+    return await TAURI_INVOKE("set_action_group_disabled", { tag, disabled });
+},
+async getActionGroupHooks() : Promise<ActionGroupHook[]> {
+    return await TAURI_INVOKE("get_action_group_hooks");
+},
+async setActionGroupHooks(hooks: ActionGroupHook[]) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_action_group_hooks", { hooks }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async addAction(action: Action) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_action", { action }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 },
 async removeAction(trigger: string) : Promise<void> {
     await TAURI_INVOKE("remove_action", { trigger });
 },
+/**
+ * Deleted actions that can still be restored, newest first
+ */
+async listTrash() : Promise<Result<TrashedAction[], ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_trash") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Returns the trigger of the restored action
+ */
+async restoreAction(id: string) : Promise<Result<string, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restore_action", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Runs the action like the simulator would with a fake user and prompt and returns what it did.
+ * Replies only show up in the transcript and nothing is sent to twitch.
+ * With dry_run nothing is changed either, it only shows what would happen.
+ */
+async testAction(action: Action, user: string, prompt: string | null, dryRun: boolean) : Promise<string[]> {
+    return await TAURI_INVOKE("test_action", { action, user, prompt, dryRun });
+},
+/**
+ * Past action runs matching the filter, newest first
+ */
+async getHistory(filter: HistoryFilter) : Promise<Result<HistoryEntry[], ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_history", { filter }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Every action file with whether it loaded, and where broken ones were moved to
+ */
+async getActionLoadReport() : Promise<ActionFileReport[]> {
+    return await TAURI_INVOKE("get_action_load_report");
+},
+/**
+ * Writes the actions with the given keys to a bundle file and returns how many were written
+ */
+async exportActions(keys: string[], path: string) : Promise<Result<number, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_actions", { keys, path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async importActions(path: string, conflict: ImportConflict) : Promise<Result<ImportReport, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_actions", { path, conflict }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getRandChatter() : Promise<string | null> {
     return await TAURI_INVOKE("get_rand_chatter");
 },
+async getChatHealth() : Promise<ChatHealth> {
+    return await TAURI_INVOKE("get_chat_health");
+},
+async getStreamSession() : Promise<StreamSession | null> {
+    return await TAURI_INVOKE("get_stream_session");
+},
+async getStreamChatters() : Promise<string[]> {
+    return await TAURI_INVOKE("get_stream_chatters");
+},
 async openLogDir() : Promise<Result<null, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("open_log_dir") };
@@ -56,6 +156,178 @@ async getRedeems() : Promise<Result<FrontendRedeem[], ErrorMsg>> {
     else return { status: "error", error: e  as any };
 }
 },
+async getRewards() : Promise<Result<FrontendReward[], ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_rewards") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async createReward(settings: RewardSettings) : Promise<Result<FrontendReward, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_reward", { settings }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async updateReward(id: string, settings: RewardSettings) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_reward", { id, settings }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteReward(id: string) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_reward", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * The keyring is unavailable and the encrypted token file needs a passphrase
+ */
+async tokenPassphraseNeeded() : Promise<boolean> {
+    return await TAURI_INVOKE("token_passphrase_needed");
+},
+/**
+ * Passphrase for the encrypted token file, used when there is no keyring.
+ * Saves the current login with it, in case it couldn't be saved without one.
+ */
+async setTokenPassphrase(passphrase: string) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_token_passphrase", { passphrase }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Features the login is missing scopes for
+ */
+async getDisabledFeatures() : Promise<Feature[]> {
+    return await TAURI_INVOKE("get_disabled_features");
+},
+/**
+ * Runs the device flow again, only asking for the missing scopes on top of the current ones
+ */
+async upgradeScopes() : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("upgrade_scopes") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Actions linked to rewards that don't exist anymore
+ */
+async getOrphanedActions() : Promise<Result<Action[], ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_orphaned_actions") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Links orphaned actions to the reward with the same title.
+ * Returns the old and new reward ids.
+ */
+async relinkRedeemActions() : Promise<Result<([string, string])[], ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("relink_redeem_actions") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async isSimulating() : Promise<boolean> {
+    return await TAURI_INVOKE("is_simulating");
+},
+async setSimulating(simulating: boolean) : Promise<void> {
+    await TAURI_INVOKE("set_simulating", { simulating });
+},
+async getTranscript() : Promise<TranscriptLine[]> {
+    return await TAURI_INVOKE("get_transcript");
+},
+async clearTranscript() : Promise<void> {
+    await TAURI_INVOKE("clear_transcript");
+},
+async simulateChat(user: string, text: string, badges: string[]) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("simulate_chat", { user, text, badges }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * The reward can be given by id or title
+ */
+async simulateRedeem(user: string, reward: string, prompt: string) : Promise<void> {
+    await TAURI_INVOKE("simulate_redeem", { user, reward, prompt });
+},
+async simulateEvent(trigger: Trigger, user: string, prompt: string | null, amount: bigint | null) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("simulate_event", { trigger, user, prompt, amount }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getRewardGroups() : Promise<RewardGroup[]> {
+    return await TAURI_INVOKE("get_reward_groups");
+},
+async setRewardGroups(groups: RewardGroup[]) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_reward_groups", { groups }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Pauses or resumes a reward group until it is resumed again, or all of them if no name is given
+ */
+async setRewardGroupPaused(name: string | null, paused: boolean) : Promise<boolean> {
+    return await TAURI_INVOKE("set_reward_group_paused", { name, paused });
+},
+async getRedeemQueue() : Promise<PendingRedemption[]> {
+    return await TAURI_INVOKE("get_redeem_queue");
+},
+async approveRedemption(id: string) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("approve_redemption", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async rejectRedemption(id: string) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reject_redemption", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Returns the ids that couldn't be approved
+ */
+async approveRedemptions(ids: string[]) : Promise<string[]> {
+    return await TAURI_INVOKE("approve_redemptions", { ids });
+},
+/**
+ * Returns the ids that couldn't be rejected
+ */
+async rejectRedemptions(ids: string[]) : Promise<string[]> {
+    return await TAURI_INVOKE("reject_redemptions", { ids });
+},
 async toggleDisableAction(key: string) : Promise<boolean | null> {
     return await TAURI_INVOKE("toggle_disable_action", { key });
 },
@@ -107,12 +379,39 @@ async setTtsBackend(backend: TtsBackend) : Promise<Result<null, ErrorMsg>> {
 },
 async getTtsCfg() : Promise<TtsConfig | null> {
     return await TAURI_INVOKE("get_tts_cfg");
+},
+async downloadPiperVoice(voice: VoiceData) : Promise<Result<null, ErrorMsg>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("download_piper_voice", { voice }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
 /** user-defined events **/
 
 
+export const events = __makeEvents__<{
+actionsChangedEvent: ActionsChangedEvent,
+authFailureEvent: AuthFailureEvent,
+chatHealthEvent: ChatHealthEvent,
+downloadEvent: DownloadEvent,
+historyEvent: HistoryEvent,
+redeemQueueEvent: RedeemQueueEvent,
+streamSessionEvent: StreamSessionEvent,
+transcriptEvent: TranscriptEvent
+}>({
+actionsChangedEvent: "actions-changed-event",
+authFailureEvent: "auth-failure-event",
+chatHealthEvent: "chat-health-event",
+downloadEvent: "download-event",
+historyEvent: "history-event",
+redeemQueueEvent: "redeem-queue-event",
+streamSessionEvent: "stream-session-event",
+transcriptEvent: "transcript-event"
+})
 
 /** user-defined constants **/
 
@@ -120,14 +419,282 @@ async getTtsCfg() : Promise<TtsConfig | null> {
 
 /** user-defined types **/
 
-export type Action = { trigger: Trigger; exec: Exec; disabled?: boolean }
+export type Action = { trigger: Trigger; exec: Exec; disabled?: boolean; redeem_policy?: RedeemPolicy; 
+/**
+ * Title of the linked reward, remembered so the action can find it again if the reward gets re-created
+ */
+reward_title?: string | null; 
+/**
+ * Also run for any reward with the same title, not just the linked one
+ */
+match_title?: boolean; 
+/**
+ * Groups the action belongs to, e.g. `games` or `moderation`, so they can be enabled or disabled together
+ */
+tags?: string[]; 
+/**
+ * Format version of the file, missing in files from before versioning
+ */
+version?: number }
+export type ActionFileError = { file: string; error: ActionLoadError }
+/**
+ * How loading a single action file went
+ */
+export type ActionFileReport = { file: string; 
+/**
+ * Key of the loaded action
+ */
+key: string | null; error: ActionLoadError | null; 
+/**
+ * Where the broken file was moved to
+ */
+quarantined: string | null }
+/**
+ * Enables or disables all actions with a tag when the stream starts or ends
+ */
+export type ActionGroupHook = { tag: string; 
+/**
+ * true enables the group, false disables it, None leaves it alone
+ */
+on_stream_start?: boolean | null; on_stream_end?: boolean | null }
+/**
+ * Why an action file couldn't be loaded
+ */
+export type ActionLoadError = { message: string; 
+/**
+ * Starts at 1
+ */
+line: bigint | null; 
+/**
+ * Starts at 1
+ */
+column: bigint | null; 
+/**
+ * Made by a newer version of the bot, the file is left alone so it still works after upgrading
+ */
+newer_version: boolean }
+/**
+ * Action files were changed outside of the bot
+ */
+export type ActionsChangedEvent = { 
+/**
+ * Keys of the added or changed actions
+ */
+updated: string[]; removed: string[]; errors: ActionFileError[] }
+/**
+ * The login stopped working and the user has to log in again
+ */
+export type AuthFailureEvent = { reason: string }
+export type ChatHealth = "Disconnected" | "Connecting" | "Connected" | "Degraded"
+export type ChatHealthEvent = { health: ChatHealth }
 export type ColorSchemeAccent = { hue: number; saturation: number; luminance: number; hex_code: string }
-export type ErrorMsg = "Unknown" | "TokenLoad" | "TokenSave" | "TwitchAuth" | "GetColorScheme" | "UsernameGone" | "TokenGone" | "ChatMsgSend" | "AlreadyLoggedIn" | "FeatureDisabled" | "WebSocketSetup" | "RedeemRequest" | "ObsWS" | "ObsOverlay" | "Tts"
+export type DownloadEvent = { id: string; current: bigint; total: bigint; percentage: number }
+export type ErrorMsg = "Unknown" | "TokenLoad" | "TokenSave" | "TwitchAuth" | "GetColorScheme" | "UsernameGone" | "TokenGone" | "ChatMsgSend" | "AlreadyLoggedIn" | "FeatureDisabled" | "WebSocketSetup" | "RedeemRequest" | "ObsWS" | "ObsOverlay" | "Tts" | "RewardNotManageable" | "MissingScope" | "PassphraseNeeded" | "UserNotFound" | "CantBanSelf" | 
+/**
+ * The action needs a prompt, e.g. the user to time out
+ */
+"MissingPrompt" | 
+/**
+ * A timeout or ban action that wasn't set up with a target
+ */
+"MissingTarget" | "Moderation"
 export type Exec = { ChatMsg: string } | { Counter: InnerCounter } | { Timeout: [ExecTarget, number] } | { Ban: ExecTarget } | { Chance: [number, Exec, Exec] } | { Tts: string }
+export type ExecStatus = "Success" | "Failed" | 
+/**
+ * Didn't run, e.g. because of the cooldown
+ */
+"Skipped"
 export type ExecTarget = "None" | "User" | "Other"
-export type FrontendRedeem = { id: string; color: string; name: string; cost: bigint }
-export type InnerCounter = { counter: number; template: string }
-export type Trigger = { Command: string } | { Redeem: string }
+/**
+ * Parts of the bot that need extra scopes
+ */
+export type Feature = "Chat" | "Redeems" | "Moderation" | "Chatters" | "Subscriptions" | "Follows" | "Bits"
+export type FrontendRedeem = { id: string; color: string; name: string; cost: bigint; manageable: boolean }
+/**
+ * Every field of a channel point reward
+ */
+export type FrontendReward = { id: string; title: string; prompt: string; cost: bigint; image: string | null; default_image: string | null; background_color: string; is_enabled: boolean; is_user_input_required: boolean; 
+/**
+ * None if there is no limit
+ */
+max_per_stream: bigint | null; 
+/**
+ * None if there is no limit
+ */
+max_per_user_per_stream: bigint | null; 
+/**
+ * None if there is no cooldown
+ */
+global_cooldown_seconds: bigint | null; is_paused: boolean; is_in_stock: boolean; should_redemptions_skip_request_queue: boolean; redemptions_redeemed_current_stream: bigint | null; cooldown_expires_at: string | null; 
+/**
+ * Created by the bot, so it can be edited and its redemptions fulfilled or refunded
+ */
+manageable: boolean }
+/**
+ * One run of an action
+ */
+export type HistoryEntry = { 
+/**
+ * Unix time in milliseconds
+ */
+at: bigint; trigger: string; user: string; prompt: string | null; status: ExecStatus; error: string | null; 
+/**
+ * What the exec tree did, step by step
+ */
+trace?: string[]; 
+/**
+ * Triggered by the simulator, nothing happened on twitch
+ */
+simulated?: boolean }
+export type HistoryEvent = { entry: HistoryEntry }
+/**
+ * Fields that are None match everything
+ */
+export type HistoryFilter = { trigger: string | null; 
+/**
+ * Case insensitive
+ */
+user: string | null; status: ExecStatus | null; 
+/**
+ * Unix time in milliseconds
+ */
+since: bigint | null; 
+/**
+ * Unix time in milliseconds
+ */
+until: bigint | null; 
+/**
+ * Only the newest entries, defaults to 200
+ */
+limit: bigint | null }
+/**
+ * What to do with an imported action if its trigger is already taken
+ */
+export type ImportConflict = 
+/**
+ * Keep the existing action
+ */
+"Skip" | 
+/**
+ * Import commands under a free name, everything else is skipped
+ */
+"Rename" | 
+/**
+ * Replace the existing action
+ */
+"Overwrite"
+export type ImportReport = { imported: string[]; skipped: string[]; 
+/**
+ * Old and new command name
+ */
+renamed: ([string, string])[]; 
+/**
+ * Redeems without a reward of the same title in this channel, they show up as orphaned actions
+ */
+unmapped: string[]; 
+/**
+ * Files written to the asset folder
+ */
+assets: string[]; 
+/**
+ * Files that already exist with a different content, the existing ones were kept
+ */
+kept_assets: string[]; 
+/**
+ * Actions or files in the bundle that couldn't be read
+ */
+errors: string[] }
+export type InnerCounter = { counter: number; template: string; reset_per_stream?: boolean }
+/**
+ * A redemption waiting for the streamer to approve or reject it
+ */
+export type PendingRedemption = { id: string; reward_id: string; reward_title: string; user: string; prompt: string; redeemed_at: bigint; 
+/**
+ * Injected by the simulator, there is nothing to fulfill or refund on twitch
+ */
+simulated?: boolean; 
+/**
+ * The reward skips the request queue, so twitch fulfilled it already and it can't be refunded.
+ * A review policy still holds back the action until it is approved.
+ */
+skipped_queue?: boolean }
+/**
+ * What happens to a channel point redemption after its action ran
+ */
+export type RedeemPolicy = 
+/**
+ * Leave it in the reward queue for the streamer
+ */
+"Manual" | 
+/**
+ * Fulfill it if the action succeeded, leave failed ones for review
+ */
+"AutoFulfill" | 
+/**
+ * Fulfill it if the action succeeded, refund the points if it failed
+ */
+"RefundOnFailure" | 
+/**
+ * Hold it in the bot's review queue, the action only runs once approved.
+ * Rewards that skip the request queue are held too, but rejecting can't refund those.
+ */
+"Review"
+export type RedeemQueueEvent = { queue: PendingRedemption[] }
+/**
+ * Rewards that get paused and resumed together
+ */
+export type RewardGroup = { name: string; 
+/**
+ * Reward ids
+ */
+rewards: string[]; 
+/**
+ * Paused while offline
+ */
+live_only?: boolean; 
+/**
+ * Only enabled while streaming one of these categories, empty means any
+ */
+categories?: string[] }
+/**
+ * Settings for creating or updating a reward, fields that are None stay untouched.
+ * For the limits and the cooldown, 0 turns them off.
+ */
+export type RewardSettings = { title: string | null; prompt: string | null; cost: bigint | null; background_color: string | null; is_enabled: boolean | null; is_paused: boolean | null; is_user_input_required: boolean | null; max_per_stream: bigint | null; max_per_user_per_stream: bigint | null; global_cooldown_seconds: bigint | null; should_redemptions_skip_request_queue: boolean | null }
+export type StreamSession = { started_at: bigint; title: string; category: string; peak_viewers: bigint }
+export type StreamSessionEvent = { session: StreamSession | null }
+export type TranscriptEvent = { line: TranscriptLine }
+export type TranscriptKind = 
+/**
+ * Injected chat message
+ */
+"Incoming" | 
+/**
+ * What the bot said
+ */
+"Outgoing" | 
+/**
+ * Something that would have happened on twitch, e.g. a timeout
+ */
+"Note"
+export type TranscriptLine = { at: bigint; user: string; text: string; kind: TranscriptKind }
+/**
+ * A deleted action waiting in the trash
+ */
+export type TrashedAction = { 
+/**
+ * File name in the trash, used to restore it
+ */
+id: string; 
+/**
+ * None if the file can't be read anymore
+ */
+trigger: string | null; 
+/**
+ * Unix time in seconds
+ */
+deleted_at: bigint }
+export type Trigger = { Command: string } | { Redeem: string } | "Follow" | "Subscribe" | "Resub" | "GiftSub" | "Cheer" | "Raid" | "StreamStart" | "StreamEnd"
 export type TtsBackend = "System" | "Piper"
 export type TtsConfig = { backend: TtsBackend; voice: VoiceData | null }
 export type VoiceData = { language: string; name: string }
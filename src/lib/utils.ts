@@ -51,6 +51,9 @@ export function toast_error(err: ErrorMsg): void {
     case "TwitchAuth":
       msg = "Error logging into Twitch";
       break;
+    case "PassphraseNeeded":
+      msg = "The login can't be saved without the right passphrase";
+      break;
     default:
       msg = "An unknown error occured. Go slap Teddy";
       break;